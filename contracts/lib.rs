@@ -22,6 +22,7 @@ use ink_lang as ink;
 
 #[ink::contract]
 pub mod prosopo {
+    use ink_prelude::collections::btree_map::BTreeMap;
     use ink_prelude::collections::btree_set::BTreeSet;
     use ink_prelude::vec::Vec;
     use ink_storage::{
@@ -32,6 +33,51 @@ pub mod prosopo {
     use rand_chacha::rand_core::SeedableRng;
     use rand_chacha::ChaChaRng;
 
+    /// Number of blocks unstaked provider funds remain slashable before they can be withdrawn
+    const DEFAULT_PROVIDER_UNBONDING_PERIOD: BlockNumber = 14_400;
+    /// Upper bound operators may set the provider unbonding period to
+    const MAX_PROVIDER_UNBONDING_PERIOD: BlockNumber = 100_800;
+    /// Providers need any stake to be Active until operators set a minimum
+    const DEFAULT_PROVIDER_STAKE: Balance = 1;
+    /// Upper bound operators may set the minimum provider stake to
+    const MAX_PROVIDER_STAKE: Balance = 1_000_000_000_000_000_000;
+    /// Upper bound operators may set the minimum operator stake to
+    const MAX_OPERATOR_STAKE: Balance = 1_000_000_000_000_000_000;
+    /// Providers may charge up to the upper bound until operators set a lower maximum fee
+    const DEFAULT_PROVIDER_FEE_MAX: u32 = MAX_PROVIDER_FEE_MAX;
+    /// Upper bound operators may set the maximum provider fee to
+    const MAX_PROVIDER_FEE_MAX: u32 = 1_000_000_000;
+    /// Providers may refund up to the upper bound until operators set a lower maximum refund
+    const DEFAULT_DAPP_REFUND_MAX: Balance = MAX_DAPP_REFUND_MAX;
+    /// Upper bound operators may set the maximum transaction fee refund to
+    const MAX_DAPP_REFUND_MAX: Balance = 1_000_000_000_000;
+    /// Number of blocks after a verdict during which it can be disputed
+    const DEFAULT_DISPUTE_WINDOW: BlockNumber = 14_400;
+    /// Upper bound operators may set the dispute window to
+    const MAX_DISPUTE_WINDOW: BlockNumber = 100_800;
+    /// Number of blocks operators have to vote on a dispute before it can be closed
+    const DEFAULT_DISPUTE_VOTING_PERIOD: BlockNumber = 14_400;
+    /// Upper bound operators may set the dispute voting period to
+    const MAX_DISPUTE_VOTING_PERIOD: BlockNumber = 100_800;
+    /// Percentage of a provider's stake that is deducted when they are slashed
+    const DEFAULT_PROVIDER_SLASH_PERCENT: u8 = 10;
+    /// Number of blocks a solution commitment may await a verdict before it can be expired
+    const DEFAULT_COMMITMENT_EXPIRY: BlockNumber = 14_400;
+    /// Upper bound operators may set the commitment expiry to
+    const MAX_COMMITMENT_EXPIRY: BlockNumber = 100_800;
+    /// Number of blocks after a commitment during which its solutions can be revealed
+    const DEFAULT_REVEAL_WINDOW: BlockNumber = 1_200;
+    /// Upper bound operators may set the reveal window to
+    const MAX_REVEAL_WINDOW: BlockNumber = 14_400;
+    /// Upper bound on the providers operators may re-evaluate in one message
+    const MAX_PROVIDER_REEVALUATION_BATCH: usize = 100;
+    /// Prefix of the hashed data of merkle tree leaves
+    const MERKLE_LEAF_PREFIX: u8 = 0x00;
+    /// Prefix of the hashed data of merkle tree nodes above the leaves
+    const MERKLE_NODE_PREFIX: u8 = 0x01;
+    /// Prefix of the hashed data of a user's salted solution tree root
+    const MERKLE_SALTED_ROOT_PREFIX: u8 = 0x02;
+
     #[derive(
         Default,
         PartialEq,
@@ -72,6 +118,107 @@ pub mod prosopo {
         Approved,
         #[default]
         Disapproved,
+        Expired,
+    }
+
+    #[derive(
+        Default,
+        PartialEq,
+        Debug,
+        Eq,
+        Clone,
+        Copy,
+        scale::Encode,
+        scale::Decode,
+        SpreadLayout,
+        PackedLayout,
+        SpreadAllocate,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub enum SolutionReveal {
+        #[default]
+        NotRequired,
+        Required,
+        Revealed,
+        Missed,
+    }
+
+    #[derive(PartialEq, Debug, Eq, Clone, Copy, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum CommitmentOutcome {
+        // a new solution commitment was stored
+        Created,
+        // the caller had already made the solution commitment, which is left unchanged
+        AlreadyExists,
+    }
+
+    #[derive(PartialEq, Debug, Eq, Clone, Copy, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Verdict {
+        Approved,
+        Disapproved,
+    }
+
+    #[derive(
+        Default,
+        PartialEq,
+        Debug,
+        Eq,
+        Clone,
+        Copy,
+        scale::Encode,
+        scale::Decode,
+        SpreadLayout,
+        PackedLayout,
+        SpreadAllocate,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub enum DisputeStatus {
+        #[default]
+        Open,
+        Upheld,
+        Rejected,
+    }
+
+    #[derive(
+        Default,
+        PartialEq,
+        Debug,
+        Eq,
+        Clone,
+        Copy,
+        scale::Encode,
+        scale::Decode,
+        SpreadLayout,
+        PackedLayout,
+        SpreadAllocate,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub enum CaptchaDataStatus {
+        Active,
+        #[default]
+        Retired,
+    }
+
+    #[derive(
+        Default,
+        PartialEq,
+        Debug,
+        Eq,
+        Clone,
+        Copy,
+        scale::Encode,
+        scale::Decode,
+        SpreadLayout,
+        PackedLayout,
+        SpreadAllocate,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub enum CaptchaType {
+        #[default]
+        ImageSelection,
+        Text,
+        ProofOfWork,
     }
 
     #[derive(
@@ -110,8 +257,7 @@ pub mod prosopo {
     )]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
     pub struct Provider {
-        // TODO how is the Status updated if the staked amount drops below the allowed minimum?
-        //    Should Status instead be a function that returns Active if staked > provider_stake_default
+        // Active providers are Suspended when their balance drops below provider_stake_default
         status: GovernanceStatus,
         // TODO should Providers have separate balances for paying/receiving fees?
         // TODO should balances be stored in self.balances under an owner, as per ERC20?
@@ -123,10 +269,87 @@ pub mod prosopo {
         captcha_dataset_id: Hash,
     }
 
+    #[derive(
+        Default,
+        PartialEq,
+        Debug,
+        Eq,
+        Clone,
+        Copy,
+        scale::Encode,
+        scale::Decode,
+        SpreadLayout,
+        PackedLayout,
+        SpreadAllocate,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct ProviderStats {
+        // solution commitments the provider has been selected to approve or disapprove
+        assigned: u64,
+        approved: u64,
+        disapproved: u64,
+        // verdicts that have been disputed
+        disputed: u64,
+        // disputes that were upheld against the provider
+        disputes_lost: u64,
+        // solution commitments the provider did not give a verdict on in time
+        timed_out: u64,
+    }
+
+    #[derive(
+        PartialEq,
+        Debug,
+        Eq,
+        Clone,
+        Copy,
+        scale::Encode,
+        scale::Decode,
+        SpreadLayout,
+        PackedLayout,
+        SpreadAllocate,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct UnbondingChunk {
+        // the amount of stake that is unbonding
+        value: Balance,
+        // the block at which the stake can be withdrawn
+        unlock_block: BlockNumber,
+    }
+
+    /// The operator governed protocol parameters
+    #[derive(PartialEq, Debug, Eq, Clone, Copy, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct ProtocolConfig {
+        // the minimum balance a provider must stake to be Active
+        provider_stake_default: Balance,
+        // the minimum balance an operator must stake
+        operator_stake_default: Balance,
+        // the maximum fee a provider may charge per captcha solution
+        provider_fee_max: u32,
+        // the maximum transaction fee that may be refunded to a dapp user per solution
+        dapp_refund_max: Balance,
+        // the number of blocks unstaked provider funds take to unbond
+        provider_unbonding_period: BlockNumber,
+        // the number of blocks after a verdict during which it can be disputed
+        dispute_window: BlockNumber,
+        // the number of blocks operators have to vote on a dispute before it can be closed
+        dispute_voting_period: BlockNumber,
+        // the percentage of a provider's stake that is deducted when they are slashed
+        provider_slash_percent: u8,
+        // whether random provider selection is weighted by reputation as well as stake
+        provider_selection_by_reputation: bool,
+        // the number of blocks a solution commitment may await a verdict before it can be expired
+        commitment_expiry: BlockNumber,
+        // the number of blocks after a commitment during which its solutions can be revealed
+        reveal_window: BlockNumber,
+    }
+
     #[derive(scale::Encode, scale::Decode)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
     pub struct RandomProvider {
         provider: Provider,
+        // the provider's dataset the user should be served captchas from
+        dataset_id: Hash,
         block_number: u32,
     }
 
@@ -163,7 +386,17 @@ pub mod prosopo {
     pub struct CaptchaData {
         provider: AccountId,
         merkle_tree_root: Hash,
-        captcha_type: u16,
+        captcha_type: CaptchaType,
+        // the number of captchas in the dataset
+        captcha_count: u32,
+        // the difficulty level of the captchas, higher is harder
+        difficulty: u16,
+        // commitment to the dataset's solutions
+        solution_hash: Hash,
+        // the block at which the dataset was registered
+        registered_block: BlockNumber,
+        // users can only commit solutions against Active datasets
+        status: CaptchaDataStatus,
     }
 
     #[derive(
@@ -190,6 +423,47 @@ pub mod prosopo {
         contract: AccountId,
         // The Provider AccountId that is permitted to approve or disapprove the commitment
         provider: AccountId,
+        // The block at which the commitment was made
+        created_block: BlockNumber,
+        // The timestamp of the block at which the commitment was made
+        created_timestamp: Timestamp,
+        // The block at which the provider approved or disapproved the commitment
+        verdict_block: BlockNumber,
+        // The timestamp of the block at which the provider approved or disapproved the commitment
+        verdict_timestamp: Timestamp,
+        // The dapp funds reserved to pay the provider's fee once a verdict is given
+        reserved: Balance,
+        // The dapp funds reserved to refund the user's transaction fee if the solution is approved
+        refund_reserved: Balance,
+        // The dapp funds paid for the provider's fee and the user's refund once approved
+        dapp_paid: Balance,
+        // The user's approved commitments preceding this one once it is approved, across all dapps
+        // and for this dapp
+        previous_correct_captcha_id: Hash,
+        previous_dapp_correct_captcha_id: Hash,
+        // Whether the user's solutions must be, or have been, revealed
+        reveal: SolutionReveal,
+    }
+
+    /// A user's revealed solution to a single captcha, with the proofs that it was committed to by
+    /// the user and that it matches the solution in the captcha dataset
+    ///
+    /// Leaves of both trees are `blake2x256(0x00 ++ captcha_id ++ solution)` and nodes are
+    /// `blake2x256(0x01 ++ left ++ right)`, so a node can never be presented as a leaf. A user
+    /// commits to `blake2x256(0x02 ++ root ++ salt)` of their tree's root. This format is defined by
+    /// the contract, off-chain tooling must build its trees the same way. Each proof layer holds the
+    /// pair of nodes hashed to form the next layer up, from the leaf to the root.
+    #[derive(PartialEq, Debug, Eq, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct CaptchaSolutionProof {
+        // the hash of the captcha that was solved
+        captcha_id: Hash,
+        // the hash of the user's solution to the captcha
+        solution: Hash,
+        // the proof that the solution is in the user's committed tree
+        commitment_proof: Vec<[Hash; 2]>,
+        // the proof that the solution is in the captcha dataset's tree
+        dataset_proof: Vec<[Hash; 2]>,
     }
 
     #[derive(
@@ -197,25 +471,101 @@ pub mod prosopo {
         Debug,
         Eq,
         Clone,
+        Copy,
         scale::Encode,
         scale::Decode,
         SpreadLayout,
         PackedLayout,
         SpreadAllocate,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct Dispute {
+        // The disputed solution commitment
+        captcha_solution_commitment_id: Hash,
+        // The Dapp User or Dapp owner that opened the dispute
+        claimant: AccountId,
+        status: DisputeStatus,
+        // The block at which the dispute was opened
+        opened_block: BlockNumber,
+        // Operator votes to overturn the provider's verdict
+        votes_uphold: u32,
+        // Operator votes to keep the provider's verdict
+        votes_reject: u32,
+    }
+
+    #[derive(
+        PartialEq,
+        Debug,
+        Eq,
+        Clone,
         Copy,
+        scale::Encode,
+        scale::Decode,
+        SpreadLayout,
+        PackedLayout,
+        SpreadAllocate,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct HumanPolicy {
+        // the minimum number of solutions with a verdict the user must have for the dapp
+        min_captchas: u64,
+        // the minimum percentage of correct solutions, in basis points
+        min_correct_bps: u16,
+        // the maximum number of blocks since the user's last correct solution, 0 for no limit
+        max_last_correct_age: BlockNumber,
+    }
+
+    impl Default for HumanPolicy {
+        fn default() -> Self {
+            HumanPolicy {
+                min_captchas: 1,
+                min_correct_bps: 8_000,
+                max_last_correct_age: 0,
+            }
+        }
+    }
+
+    #[derive(
+        PartialEq,
+        Debug,
+        Eq,
+        Clone,
+        scale::Encode,
+        scale::Decode,
+        SpreadLayout,
+        PackedLayout,
+        SpreadAllocate,
     )]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
     pub struct Dapp {
         status: GovernanceStatus,
         // TODO should balances be stored in self.balances under an owner, as per ERC20?
         balance: Balance,
+        // the portion of the balance reserved for fees and refunds due on pending commitments
+        reserved: Balance,
+        // the number of commitments awaiting a verdict from a provider
+        pending_commitments: u32,
+        // the transaction fee refund reserved for each commitment to the dapp
+        refund_reserve: Balance,
         owner: AccountId,
+        // whether the dapp contract has proven that it controls the registration
+        verified: bool,
+        // the minimum difficulty of the datasets the dapp's users are served
         min_difficulty: u16,
+        // the minimum number of captchas in the datasets the dapp's users are served
+        min_captcha_count: u32,
+        // the captcha types the dapp accepts, any type is accepted if empty
+        captcha_types: Vec<CaptchaType>,
+        // the policy dapp users must meet to be considered human by the dapp
+        human_policy: HumanPolicy,
+        // whether the dapp's users must reveal their solutions after committing them
+        solution_reveal_required: bool,
         // client's Dapp URL
         client_origin: Hash,
     }
 
     #[derive(
+        Default,
         PartialEq,
         Debug,
         Eq,
@@ -231,11 +581,11 @@ pub mod prosopo {
     pub struct User {
         correct_captchas: u64,
         incorrect_captchas: u64,
-        // commented until block timestamp is available in ink unit tests
-        // created: Timestamp,
-        // updated: Timestamp,
-        //last_correct_captcha: Timestamp,
-        //last_correct_captcha_dapp_id: AccountId,
+        created: Timestamp,
+        updated: Timestamp,
+        // the commitment of the most recent approved solution that still stands, its verdict block
+        // and timestamp are those of the user's last correct captcha
+        last_correct_captcha_id: Hash,
     }
 
     // Contract storage
@@ -247,19 +597,42 @@ pub mod prosopo {
         provider_accounts: Mapping<GovernanceStatus, BTreeSet<AccountId>>,
         service_origins: Mapping<Hash, ()>,
         captcha_data: Mapping<Hash, CaptchaData>,
+        provider_datasets: Mapping<AccountId, Vec<Hash>>,
         captcha_solution_commitments: Mapping<Hash, CaptchaSolutionCommitment>,
         provider_stake_default: u128,
+        provider_fee_max: u32,
+        provider_stats: Mapping<AccountId, ProviderStats>,
+        provider_unbonding: Mapping<AccountId, Vec<UnbondingChunk>>,
+        provider_unbonding_period: BlockNumber,
         dapps: Mapping<AccountId, Dapp>,
         dapp_accounts: Vec<AccountId>,
+        dapp_pending_owners: Mapping<AccountId, AccountId>,
+        dapp_provider_allow_lists: Mapping<AccountId, BTreeSet<AccountId>>,
+        dapp_provider_deny_lists: Mapping<AccountId, BTreeSet<AccountId>>,
         //dapps_owners: Mapping<AccountId, AccountId>,
         operators: Mapping<AccountId, Operator>,
         operator_accounts: Vec<AccountId>,
-        //disputes: Mapping<u64, Dispute>
+        disputes: Mapping<u64, Dispute>,
+        dispute_count: u64,
+        dispute_votes: Mapping<(u64, AccountId), bool>,
+        commitment_disputes: Mapping<Hash, u64>,
+        dispute_window: BlockNumber,
+        dispute_voting_period: BlockNumber,
+        provider_slash_percent: u8,
+        provider_selection_by_reputation: bool,
+        commitment_expiry: BlockNumber,
+        reveal_window: BlockNumber,
+        // the solutions revealed for solution commitments that require a reveal
+        commitment_solutions: Mapping<Hash, Vec<(Hash, Hash)>>,
+        treasury_balance: Balance,
         status: GovernanceStatus,
-        operator_stake_default: u64,
+        operator_stake_default: Balance,
         operator_fee_currency: Hash,
+        dapp_refund_max: Balance,
         dapp_users: Mapping<AccountId, User>,
         dapp_user_accounts: Vec<AccountId>,
+        // dapp user records scoped to a single dapp, keyed on (dapp contract, dapp user)
+        dapp_user_dapp_records: Mapping<(AccountId, AccountId), User>,
     }
 
     // Event emitted when a new provider registers
@@ -295,6 +668,26 @@ pub mod prosopo {
         value: Balance,
     }
 
+    // Event emitted when a provider's status changes as a result of a change in their balance
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct ProviderStatusChange {
+        #[ink(topic)]
+        account: AccountId,
+        status: GovernanceStatus,
+    }
+
+    // Event emitted when a provider is slashed
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct ProviderSlashed {
+        #[ink(topic)]
+        account: AccountId,
+        value: Balance,
+        // the harmed party that received the slashed funds, or None if paid into the treasury
+        beneficiary: Option<AccountId>,
+    }
+
     // Event emitted when a provider adds a data set
     #[ink(event)]
     #[derive(Debug)]
@@ -304,6 +697,15 @@ pub mod prosopo {
         merkle_tree_root: Hash,
     }
 
+    // Event emitted when a provider retires a data set
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct ProviderRetireDataset {
+        #[ink(topic)]
+        account: AccountId,
+        merkle_tree_root: Hash,
+    }
+
     // Event emitted when a provider unstakes
     #[ink(event)]
     #[derive(Debug)]
@@ -313,6 +715,15 @@ pub mod prosopo {
         value: Balance,
     }
 
+    // Event emitted when a provider withdraws unbonded stake
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct ProviderWithdrawUnbonded {
+        #[ink(topic)]
+        account: AccountId,
+        value: Balance,
+    }
+
     // Event emitted when a provider approves a solution
     #[ink(event)]
     #[derive(Debug)]
@@ -329,6 +740,40 @@ pub mod prosopo {
         captcha_solution_commitment_id: Hash,
     }
 
+    // Event emitted when a dapp owner sets whether solutions must be revealed
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct DappSolutionRevealUpdate {
+        #[ink(topic)]
+        contract: AccountId,
+        required: bool,
+    }
+
+    // Event emitted when a user's solutions are revealed
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct SolutionRevealed {
+        #[ink(topic)]
+        captcha_solution_commitment_id: Hash,
+    }
+
+    // Event emitted when a solution commitment is failed as its solutions were not revealed in time
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct SolutionRevealMissed {
+        #[ink(topic)]
+        captcha_solution_commitment_id: Hash,
+    }
+
+    // Event emitted when a solution commitment expires without a verdict
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct CommitmentExpire {
+        #[ink(topic)]
+        captcha_solution_commitment_id: Hash,
+        provider: AccountId,
+    }
+
     // Event emitted when a dapp registers
     #[ink(event)]
     #[derive(Debug)]
@@ -351,44 +796,197 @@ pub mod prosopo {
         value: Balance,
     }
 
-    // Event emitted when a dapp funds
+    // Event emitted when a dapp owner updates the providers permitted to serve the dapp
     #[ink(event)]
     #[derive(Debug)]
-    pub struct DappFund {
+    pub struct DappProviderListUpdate {
+        #[ink(topic)]
+        contract: AccountId,
+        allow_list: Vec<AccountId>,
+        deny_list: Vec<AccountId>,
+    }
+
+    // Event emitted when a dapp owner updates the dapp's human verification policy
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct DappHumanPolicyUpdate {
+        #[ink(topic)]
+        contract: AccountId,
+        policy: HumanPolicy,
+    }
+
+    // Event emitted when a dapp owner proposes a new owner
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct DappOwnershipProposed {
+        #[ink(topic)]
+        contract: AccountId,
+        owner: AccountId,
+        pending_owner: AccountId,
+    }
+
+    // Event emitted when a proposed owner accepts ownership of a dapp
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct DappOwnershipTransferred {
+        #[ink(topic)]
+        contract: AccountId,
+        previous_owner: AccountId,
+        owner: AccountId,
+    }
+
+    // Event emitted when a proposed ownership transfer is cancelled
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct DappOwnershipTransferCancelled {
+        #[ink(topic)]
+        contract: AccountId,
+        pending_owner: AccountId,
+    }
+
+    // Event emitted when a dapp owner reactivates a deactivated dapp
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct DappReactivate {
         #[ink(topic)]
         contract: AccountId,
         value: Balance,
     }
 
-    // Event emitted when a dapp cancels
+    // Event emitted when a dapp owner removes a deactivated dapp
     #[ink(event)]
     #[derive(Debug)]
-    pub struct DappCancel {
+    pub struct DappRemove {
         #[ink(topic)]
         contract: AccountId,
         value: Balance,
     }
 
-    // Event emitted when a dapp user commits a solution hash
+    // Event emitted when a dapp contract proves control of its registration
     #[ink(event)]
     #[derive(Debug)]
-    pub struct DappUserCommit {
+    pub struct DappVerify {
         #[ink(topic)]
-        account: AccountId,
-        merkle_tree_root: Hash,
         contract: AccountId,
-        captcha_dataset_id: Hash,
+        owner: AccountId,
     }
 
-    /// The Prosopo error types
-    #[derive(PartialEq, Debug, Eq, Clone, scale::Encode, scale::Decode)]
-    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
-    pub enum Error {
-        /// Returned if calling account is not authorised to perform action
-        NotAuthorised,
-        /// Returned if not enough contract balance to fulfill a request is available.
-        ContractInsufficientFunds,
-        /// Returned when the contract to address transfer fails
+    // Event emitted when a dapp owner configures the dapp's captcha requirements
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct DappConfigure {
+        #[ink(topic)]
+        contract: AccountId,
+        min_difficulty: u16,
+        min_captcha_count: u32,
+        captcha_types: Vec<CaptchaType>,
+    }
+
+    // Event emitted when a dapp funds
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct DappFund {
+        #[ink(topic)]
+        contract: AccountId,
+        value: Balance,
+    }
+
+    // Event emitted when a dapp owner sets the transaction fee refund reserved per commitment
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct DappRefundReserveUpdate {
+        #[ink(topic)]
+        contract: AccountId,
+        value: Balance,
+    }
+
+    // Event emitted when a dapp owner withdraws funds
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct DappWithdraw {
+        #[ink(topic)]
+        contract: AccountId,
+        value: Balance,
+    }
+
+    // Event emitted when a dapp cancels
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct DappCancel {
+        #[ink(topic)]
+        contract: AccountId,
+        value: Balance,
+    }
+
+    // Event emitted when a dapp user or dapp owner disputes a provider's verdict
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct DisputeOpen {
+        #[ink(topic)]
+        dispute_id: u64,
+        captcha_solution_commitment_id: Hash,
+        claimant: AccountId,
+    }
+
+    // Event emitted when an operator votes on a dispute
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct DisputeVote {
+        #[ink(topic)]
+        dispute_id: u64,
+        operator: AccountId,
+        uphold: bool,
+    }
+
+    // Event emitted when a dispute is resolved
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct DisputeResolve {
+        #[ink(topic)]
+        dispute_id: u64,
+        status: DisputeStatus,
+    }
+
+    // Event emitted when an operator updates the protocol config
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct ProtocolConfigUpdate {
+        #[ink(topic)]
+        operator: AccountId,
+        config: ProtocolConfig,
+    }
+
+    // Event emitted when an operator pays slashed stake out of the protocol treasury
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct TreasuryWithdraw {
+        #[ink(topic)]
+        operator: AccountId,
+        #[ink(topic)]
+        to: AccountId,
+        value: Balance,
+    }
+
+    // Event emitted when a dapp user commits a solution hash
+    #[ink(event)]
+    #[derive(Debug)]
+    pub struct DappUserCommit {
+        #[ink(topic)]
+        account: AccountId,
+        merkle_tree_root: Hash,
+        contract: AccountId,
+        captcha_dataset_id: Hash,
+    }
+
+    /// The Prosopo error types
+    #[derive(PartialEq, Debug, Eq, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        /// Returned if calling account is not authorised to perform action
+        NotAuthorised,
+        /// Returned if not enough contract balance to fulfill a request is available.
+        ContractInsufficientFunds,
+        /// Returned when the contract to address transfer fails
         ContractTransferFailed,
         /// Returned if provider exists when it shouldn't
         ProviderExists,
@@ -408,18 +1006,84 @@ pub mod prosopo {
         DappDoesNotExist,
         /// Returned if dapp is inactive and trying to use the service
         DappInactive,
+        /// Returned if the dapp's registration has not been verified by the dapp contract
+        DappNotVerified,
         /// Returned if dapp has insufficient funds to operate
         DappInsufficientFunds,
         /// Returned if captcha data does not exist
         CaptchaDataDoesNotExist,
         /// Returned if solution commitment does not exist when it should
         CaptchaSolutionCommitmentDoesNotExist,
-        /// Returned if solution commitment already exists when it should not
+        /// Returned if solution commitment already exists for a different dapp user
         CaptchaSolutionCommitmentExists,
         /// Returned if dapp user does not exist when it should
         DappUserDoesNotExist,
         /// Returned if there are no active providers
         NoActiveProviders,
+        /// Returned if a zero or otherwise invalid amount is requested
+        InvalidAmount,
+        /// Returned if the treasury holds less than the amount requested
+        TreasuryInsufficientFunds,
+        /// Returned if a protocol config parameter is out of bounds
+        InvalidProtocolConfig,
+        /// Returned if more providers are given than can be processed in one message
+        TooManyProviders,
+        /// Returned if a provider fee is greater than the maximum provider fee
+        ProviderFeeTooHigh,
+        /// Returned if a transaction fee refund is greater than the maximum refund
+        TransactionFeeRefundTooHigh,
+        /// Returned if solution commitment has not been approved or disapproved yet
+        CaptchaSolutionCommitmentPending,
+        /// Returned if solution commitment is no longer awaiting a verdict
+        CaptchaSolutionCommitmentNotPending,
+        /// Returned if solution commitment has not yet reached its expiry
+        CaptchaSolutionCommitmentNotExpired,
+        /// Returned if solution commitment expired without a verdict
+        CaptchaSolutionCommitmentExpired,
+        /// Returned if a revealed solution is not part of the user's solution commitment
+        InvalidSolutionCommitmentProof,
+        /// Returned if the solution commitment does not await a reveal of its solutions
+        SolutionRevealNotRequired,
+        /// Returned if the revealed solutions do not hash to the committed root
+        InvalidSolutionReveal,
+        /// Returned if the reveal window of the solution commitment has closed
+        SolutionRevealWindowClosed,
+        /// Returned if the reveal window of the solution commitment is still open
+        SolutionRevealWindowOpen,
+        /// Returned if the solution commitment failed because its solutions were not revealed
+        SolutionRevealMissed,
+        /// Returned if dispute does not exist when it should
+        DisputeDoesNotExist,
+        /// Returned if dispute already exists when it should not
+        DisputeExists,
+        /// Returned if the window for disputing a verdict has passed
+        DisputeWindowClosed,
+        /// Returned if the dispute has already been resolved
+        DisputeClosed,
+        /// Returned if the operator has already voted on the dispute
+        DisputeAlreadyVoted,
+        /// Returned if the voting period of the dispute has ended
+        DisputeVotingPeriodEnded,
+        /// Returned if the voting period of the dispute has not ended yet
+        DisputeVotingPeriodOpen,
+        /// Returned if captcha data belongs to a different provider
+        CaptchaDataProviderMismatch,
+        /// Returned if captcha data has been retired by its provider
+        CaptchaDataRetired,
+        /// Returned if captcha data is added again with different metadata
+        CaptchaDataMetadataMismatch,
+        /// Returned if the dapp has no pending owner
+        DappNoPendingOwner,
+        /// Returned if captcha data does not meet the dapp's captcha requirements
+        CaptchaDataDoesNotMeetDappRequirements,
+        /// Returned if the provider is excluded by the dapp's allow-list or deny-list
+        ProviderNotPermittedByDapp,
+        /// Returned if a human verification policy parameter is out of bounds
+        InvalidHumanPolicy,
+        /// Returned if the dapp must be deactivated for the operation
+        DappNotDeactivated,
+        /// Returned if the dapp has commitments awaiting a verdict
+        DappHasPendingCommitments,
     }
 
     impl Prosopo {
@@ -436,6 +1100,15 @@ pub mod prosopo {
             };
             self.operators.insert(operator_account, &operator);
             self.operator_accounts.push(operator_account);
+            self.provider_unbonding_period = DEFAULT_PROVIDER_UNBONDING_PERIOD;
+            self.provider_stake_default = DEFAULT_PROVIDER_STAKE;
+            self.provider_fee_max = DEFAULT_PROVIDER_FEE_MAX;
+            self.dapp_refund_max = DEFAULT_DAPP_REFUND_MAX;
+            self.dispute_window = DEFAULT_DISPUTE_WINDOW;
+            self.dispute_voting_period = DEFAULT_DISPUTE_VOTING_PERIOD;
+            self.provider_slash_percent = DEFAULT_PROVIDER_SLASH_PERCENT;
+            self.commitment_expiry = DEFAULT_COMMITMENT_EXPIRY;
+            self.reveal_window = DEFAULT_REVEAL_WINDOW;
         }
 
         /// Register a provider, their service origin and fee
@@ -457,6 +1130,9 @@ pub mod prosopo {
             if self.providers.get(&provider_account).is_some() {
                 return Ok(());
             }
+            if fee > self.provider_fee_max {
+                return Err(Error::ProviderFeeTooHigh);
+            }
             // prevent duplicate service origins
             if self.service_origins.get(&service_origin).is_some() {
                 return Err(Error::ProviderServiceOriginUsed);
@@ -507,6 +1183,10 @@ pub mod prosopo {
                 return Err(Error::ProviderDoesNotExist);
             }
 
+            if fee > self.provider_fee_max {
+                return Err(Error::ProviderFeeTooHigh);
+            }
+
             let existing = self.get_provider_details(provider_account).unwrap();
 
             // prevent duplicate service origins
@@ -523,7 +1203,11 @@ pub mod prosopo {
             let mut new_status = existing.status;
             let balance = existing.balance + self.env().transferred_value();
 
-            if balance >= self.provider_stake_default {
+            // deactivated providers are reactivated once they have staked enough, moves between
+            // Active and Suspended are left to `provider_reevaluate_status`
+            if old_status == GovernanceStatus::Deactivated
+                && self.provider_meets_minimum_stake(balance)
+            {
                 new_status = GovernanceStatus::Active;
             }
 
@@ -539,6 +1223,7 @@ pub mod prosopo {
 
             self.provider_change_status(provider_account, old_status, new_status);
             self.providers.insert(provider_account, &provider);
+            self.provider_reevaluate_status(provider_account);
 
             self.env().emit_event(ProviderUpdate {
                 account: provider_account,
@@ -546,6 +1231,39 @@ pub mod prosopo {
             Ok(())
         }
 
+        /// Returns true if `balance` is enough for a provider to be Active
+        fn provider_meets_minimum_stake(&self, balance: Balance) -> bool {
+            balance > 0 && balance >= self.provider_stake_default
+        }
+
+        /// Move a provider between Active and Suspended according to their current balance
+        ///
+        /// Must be called after every change to a provider's balance. Deactivated providers are
+        /// left untouched as they can only be reactivated through `provider_update`.
+        fn provider_reevaluate_status(&mut self, provider_account: AccountId) {
+            let mut provider = match self.providers.get(&provider_account) {
+                Some(provider) => provider,
+                None => return,
+            };
+            if provider.status == GovernanceStatus::Deactivated {
+                return;
+            }
+            let new_status = if self.provider_meets_minimum_stake(provider.balance) {
+                GovernanceStatus::Active
+            } else {
+                GovernanceStatus::Suspended
+            };
+            if new_status != provider.status {
+                self.provider_change_status(provider_account, provider.status, new_status);
+                provider.status = new_status;
+                self.providers.insert(provider_account, &provider);
+                self.env().emit_event(ProviderStatusChange {
+                    account: provider_account,
+                    status: new_status,
+                });
+            }
+        }
+
         /// Switch the `provider_account` between indexes in `self.provider_accounts`
         fn provider_change_status(
             &mut self,
@@ -604,50 +1322,182 @@ pub mod prosopo {
             Ok(())
         }
 
-        // TODO allow Provider to unstake(withdraw) less than they have staked
-        /// Unstake and deactivate the provider's service, returning stake
+        /// Unstake `amount` from the provider's stake, moving it into the unbonding queue
+        ///
+        /// The provider is deactivated once their entire stake has been unstaked
         #[ink(message)]
-        #[ink(payable)]
-        pub fn provider_unstake(&mut self) -> Result<(), Error> {
+        pub fn provider_unstake(&mut self, amount: Balance) -> Result<(), Error> {
             let caller = self.env().caller();
             // TODO should the operators be able to do this ?
-            if self.providers.get(&caller).is_some() {
-                let provider = self.get_provider_details(caller)?;
-                let balance = provider.balance;
-                if balance > 0 {
-                    self.env().transfer(caller, balance).ok();
-                    self.provider_deregister(caller)?;
-                    self.env().emit_event(ProviderUnstake {
-                        account: caller,
-                        value: balance,
-                    });
-                }
+            let mut provider = self.get_provider_details(caller)?;
+            if amount == 0 {
+                return Err(Error::InvalidAmount);
+            }
+            if amount > provider.balance {
+                return Err(Error::ProviderInsufficientFunds);
+            }
+            provider.balance -= amount;
+            self.providers.insert(caller, &provider);
+
+            // unstaked funds remain slashable until the unbonding period has passed
+            let unlock_block = self.env().block_number() + self.provider_unbonding_period;
+            let mut unbonding = self.provider_unbonding.get(caller).unwrap_or_default();
+            match unbonding
+                .iter_mut()
+                .find(|chunk| chunk.unlock_block == unlock_block)
+            {
+                Some(chunk) => chunk.value += amount,
+                None => unbonding.push(UnbondingChunk {
+                    value: amount,
+                    unlock_block,
+                }),
+            }
+            self.provider_unbonding.insert(caller, &unbonding);
+
+            if provider.balance == 0 {
+                self.provider_deregister(caller)?;
             } else {
-                return Err(Error::ProviderDoesNotExist);
+                self.provider_reevaluate_status(caller);
             }
+
+            self.env().emit_event(ProviderUnstake {
+                account: caller,
+                value: amount,
+            });
             Ok(())
         }
 
-        /// Add a new data set
+        /// Withdraw all unbonding stake whose unbonding period has passed
+        #[ink(message)]
+        pub fn provider_withdraw_unbonded(&mut self) -> Result<Balance, Error> {
+            let caller = self.env().caller();
+            self.get_provider_details(caller)?;
+            let current_block = self.env().block_number();
+            let unbonding = self.provider_unbonding.get(caller).unwrap_or_default();
+            let (matured, remaining): (Vec<UnbondingChunk>, Vec<UnbondingChunk>) = unbonding
+                .into_iter()
+                .partition(|chunk| chunk.unlock_block <= current_block);
+            let value: Balance = matured.iter().map(|chunk| chunk.value).sum();
+            if value == 0 {
+                return Ok(0);
+            }
+            if self.env().transfer(caller, value).is_err() {
+                return Err(Error::ContractTransferFailed);
+            }
+            self.provider_unbonding.insert(caller, &remaining);
+            self.env().emit_event(ProviderWithdrawUnbonded {
+                account: caller,
+                value,
+            });
+            Ok(value)
+        }
+
+        /// Deduct `provider_slash_percent` of the provider's stake, including unbonding stake,
+        /// and pay it to the `beneficiary` or into the treasury
+        fn provider_slash(
+            &mut self,
+            provider_account: AccountId,
+            beneficiary: Option<AccountId>,
+        ) -> Result<Balance, Error> {
+            let mut provider = self.get_provider_details(provider_account)?;
+            let mut unbonding = self
+                .provider_unbonding
+                .get(provider_account)
+                .unwrap_or_default();
+            let slashable: Balance =
+                provider.balance + unbonding.iter().map(|chunk| chunk.value).sum::<Balance>();
+            let percent = Balance::from(self.provider_slash_percent);
+            let value = slashable / 100 * percent + slashable % 100 * percent / 100;
+            if value == 0 {
+                return Ok(0);
+            }
+
+            if let Some(account) = beneficiary {
+                if self.env().transfer(account, value).is_err() {
+                    return Err(Error::ContractTransferFailed);
+                }
+            } else {
+                self.treasury_balance += value;
+            }
+
+            // take the slash from the staked balance first and then from the unbonding stake,
+            // starting with the stake that would unlock last
+            let from_balance = core::cmp::min(provider.balance, value);
+            provider.balance -= from_balance;
+            let mut remaining = value - from_balance;
+            unbonding.sort_by_key(|chunk| chunk.unlock_block);
+            for chunk in unbonding.iter_mut().rev() {
+                let from_chunk = core::cmp::min(chunk.value, remaining);
+                chunk.value -= from_chunk;
+                remaining -= from_chunk;
+            }
+            unbonding.retain(|chunk| chunk.value > 0);
+            self.providers.insert(provider_account, &provider);
+            self.provider_unbonding.insert(provider_account, &unbonding);
+            self.provider_reevaluate_status(provider_account);
+
+            self.env().emit_event(ProviderSlashed {
+                account: provider_account,
+                value,
+                beneficiary,
+            });
+            Ok(value)
+        }
+
+        /// Add a new data set to the provider's catalogue, or reactivate a retired one
+        ///
+        /// A data set the provider already added keeps the metadata it was registered with. Adding
+        /// it again with the same metadata reactivates it, adding it with different metadata is
+        /// rejected rather than changing the metadata of a data set commitments may be pinned to
         #[ink(message)]
-        pub fn provider_add_dataset(&mut self, merkle_tree_root: Hash) -> Result<(), Error> {
+        pub fn provider_add_dataset(
+            &mut self,
+            merkle_tree_root: Hash,
+            captcha_type: CaptchaType,
+            captcha_count: u32,
+            difficulty: u16,
+            solution_hash: Hash,
+        ) -> Result<(), Error> {
             let provider_id = self.env().caller();
             // the calling account must belong to the provider
             // TODO add Prosopo operators? Currently, only a provider can add a data set for themselves.
             self.validate_provider(provider_id)?;
 
-            let dataset = CaptchaData {
-                provider: provider_id,
-                merkle_tree_root,
-                captcha_type: 0,
-            };
-
-            // create a new id and insert details of the new captcha data set if it doesn't exist
-            if self.captcha_data.get(merkle_tree_root).is_none() {
-                self.captcha_data.insert(merkle_tree_root, &dataset);
+            match self.captcha_data.get(merkle_tree_root) {
+                Some(existing) if existing.provider != provider_id => {
+                    return Err(Error::DuplicateCaptchaDataId);
+                }
+                Some(existing)
+                    if existing.captcha_type != captcha_type
+                        || existing.captcha_count != captcha_count
+                        || existing.difficulty != difficulty
+                        || existing.solution_hash != solution_hash =>
+                {
+                    return Err(Error::CaptchaDataMetadataMismatch);
+                }
+                Some(mut existing) => {
+                    existing.status = CaptchaDataStatus::Active;
+                    self.captcha_data.insert(merkle_tree_root, &existing);
+                }
+                None => {
+                    let dataset = CaptchaData {
+                        provider: provider_id,
+                        merkle_tree_root,
+                        captcha_type,
+                        captcha_count,
+                        difficulty,
+                        solution_hash,
+                        registered_block: self.env().block_number(),
+                        status: CaptchaDataStatus::Active,
+                    };
+                    self.captcha_data.insert(merkle_tree_root, &dataset);
+                    let mut datasets = self.provider_datasets.get(provider_id).unwrap_or_default();
+                    datasets.push(merkle_tree_root);
+                    self.provider_datasets.insert(provider_id, &datasets);
+                }
             }
 
-            // set the captcha data id on the provider
+            // set the most recently added captcha data id on the provider
             let mut provider = self.providers.get(&provider_id).unwrap();
             provider.captcha_dataset_id = merkle_tree_root;
             self.providers.insert(provider_id, &provider);
@@ -661,6 +1511,37 @@ pub mod prosopo {
             Ok(())
         }
 
+        /// Retire a data set so that users can no longer commit solutions against it
+        #[ink(message)]
+        pub fn provider_retire_dataset(&mut self, merkle_tree_root: Hash) -> Result<(), Error> {
+            let provider_id = self.env().caller();
+            let mut provider = self.get_provider_details(provider_id)?;
+            let mut dataset = self.get_captcha_data(merkle_tree_root)?;
+            if dataset.provider != provider_id {
+                return Err(Error::NotAuthorised);
+            }
+            dataset.status = CaptchaDataStatus::Retired;
+            self.captcha_data.insert(merkle_tree_root, &dataset);
+
+            // fall back to the most recently added dataset that is still active
+            if provider.captcha_dataset_id == merkle_tree_root {
+                provider.captcha_dataset_id = self
+                    .list_provider_datasets(provider_id)
+                    .iter()
+                    .rev()
+                    .find(|dataset| dataset.status == CaptchaDataStatus::Active)
+                    .map(|dataset| dataset.merkle_tree_root)
+                    .unwrap_or_default();
+                self.providers.insert(provider_id, &provider);
+            }
+
+            self.env().emit_event(ProviderRetireDataset {
+                account: provider_id,
+                merkle_tree_root,
+            });
+            Ok(())
+        }
+
         /// Register a dapp
         #[ink(message)]
         pub fn dapp_register(
@@ -681,12 +1562,21 @@ pub mod prosopo {
                 } else {
                     GovernanceStatus::Suspended
                 };
-                // TODO this means that any caller can register any contract.. is this an issue?
+                // registrations made by anyone other than the dapp contract itself are unverified
+                // until the contract calls `dapp_verify`, users cannot commit solutions to them
                 let dapp = Dapp {
                     status,
                     balance: transferred,
-                    owner,
+                    reserved: 0,
+                    pending_commitments: 0,
+                    refund_reserve: 0,
+                    owner: caller,
+                    verified: caller == contract,
                     min_difficulty: 1,
+                    min_captcha_count: 0,
+                    captcha_types: Vec::new(),
+                    human_policy: HumanPolicy::default(),
+                    solution_reveal_required: false,
                     client_origin,
                 };
                 // keying on contract allows owners to own many contracts
@@ -695,10 +1585,14 @@ pub mod prosopo {
                 // emit event
                 self.env().emit_event(DappRegister {
                     contract,
-                    owner,
+                    owner: caller,
                     client_origin,
                     value: transferred,
                 });
+                // the caller owns the funded dapp until a different owner accepts ownership
+                if owner != caller {
+                    self.dapp_set_pending_owner(contract, caller, owner);
+                }
             } else {
                 // dapp exists so update it instead
                 self.dapp_update(owner, transferred, client_origin, contract, caller);
@@ -706,6 +1600,8 @@ pub mod prosopo {
         }
 
         /// Update a dapp with new funds, setting status as appropriate
+        ///
+        /// A different `owner` is proposed as the new owner rather than replacing the current one
         fn dapp_update(
             &mut self,
             owner: AccountId,
@@ -716,12 +1612,12 @@ pub mod prosopo {
         ) {
             if self.dapps.get(&contract).is_some() {
                 let mut dapp = self.dapps.get(&contract).unwrap();
-                // only allow the owner to make changes to the dapp (including funding?!)
-                if dapp.owner == caller {
+                // only allow the owner to make changes to the dapp (including funding?!), deactivated
+                // dapps must be reactivated with `dapp_reactivate`
+                if dapp.owner == caller && dapp.status != GovernanceStatus::Deactivated {
                     let total = dapp.balance + transferred;
                     dapp.balance = total;
                     dapp.client_origin = client_origin;
-                    dapp.owner = owner;
                     if dapp.balance > 0 {
                         dapp.status = GovernanceStatus::Active;
                     } else {
@@ -731,119 +1627,445 @@ pub mod prosopo {
                     // emit event
                     self.env().emit_event(DappUpdate {
                         contract,
-                        owner,
+                        owner: dapp.owner,
                         client_origin,
                         value: total,
                     });
+                    if owner != dapp.owner {
+                        self.dapp_set_pending_owner(contract, dapp.owner, owner);
+                    }
                 } else {
-                    //return the transferred balance to the caller as they do not own the contract
+                    //return the transferred balance to the caller as they do not own the contract or
+                    //the dapp is deactivated
                     self.env().transfer(caller, transferred).ok();
                 }
             }
         }
 
-        /// Fund dapp account to pay for services, if the Dapp caller is registered in self.dapps
+        /// Set the captcha requirements the datasets served to the dapp's users must meet
+        ///
+        /// An empty `captcha_types` accepts any captcha type
         #[ink(message)]
-        #[ink(payable)]
-        pub fn dapp_fund(&mut self, contract: AccountId) {
+        pub fn dapp_configure(
+            &mut self,
+            contract: AccountId,
+            min_difficulty: u16,
+            min_captcha_count: u32,
+            captcha_types: Vec<CaptchaType>,
+        ) -> Result<(), Error> {
             let caller = self.env().caller();
-            let transferred = self.env().transferred_value();
-            if self.dapps.get(&contract).is_some() {
-                let mut dapp = self.dapps.get(&contract).unwrap();
-                let total = dapp.balance + transferred;
-                dapp.balance = total;
-                if dapp.balance > 0 {
-                    dapp.status = GovernanceStatus::Active;
-                    self.env().emit_event(DappFund {
-                        contract,
-                        value: total,
-                    });
-                } else {
-                    // Suspended as dapp has no funds
-                    dapp.status = GovernanceStatus::Suspended;
-                }
-                self.dapps.insert(contract, &dapp);
-            } else {
-                //return the transferred balance to the caller
-                self.env().transfer(caller, transferred).ok();
+            let mut dapp = self.get_dapp_details(contract)?;
+            if dapp.owner != caller {
+                return Err(Error::NotAuthorised);
             }
+            dapp.min_difficulty = min_difficulty;
+            dapp.min_captcha_count = min_captcha_count;
+            dapp.captcha_types = captcha_types.clone();
+            self.dapps.insert(contract, &dapp);
+            self.env().emit_event(DappConfigure {
+                contract,
+                min_difficulty,
+                min_captcha_count,
+                captcha_types,
+            });
+            Ok(())
         }
 
-        /// Cancel services as a dapp, returning remaining tokens
+        /// Set the providers that may serve the dapp's users
+        ///
+        /// Any provider that is not denied may serve the dapp if the allow-list is empty
         #[ink(message)]
-        pub fn dapp_cancel(&mut self, contract: AccountId) -> Result<(), Error> {
+        pub fn dapp_set_provider_allow_list(
+            &mut self,
+            contract: AccountId,
+            providers: Vec<AccountId>,
+        ) -> Result<(), Error> {
             let caller = self.env().caller();
-
-            if self.dapps.get(&contract).is_none() {
-                return Err(Error::DappDoesNotExist);
-            }
             let dapp = self.get_dapp_details(contract)?;
-
-            // TODO should the operators be authorised to do this ?
-            // TODO If an owner is not specified then the Dapp contract can never be cancelled
             if dapp.owner != caller {
                 return Err(Error::NotAuthorised);
             }
+            self.dapp_provider_allow_lists
+                .insert(contract, &providers.into_iter().collect::<BTreeSet<_>>());
+            self.emit_dapp_provider_list_update(contract);
+            Ok(())
+        }
 
-            let balance = dapp.balance;
-            // TODO ensure that the dapp has no outstanding payments due
-            if dapp.balance > 0 {
-                self.env().transfer(caller, dapp.balance).ok();
+        /// Set the providers that may not serve the dapp's users
+        #[ink(message)]
+        pub fn dapp_set_provider_deny_list(
+            &mut self,
+            contract: AccountId,
+            providers: Vec<AccountId>,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let dapp = self.get_dapp_details(contract)?;
+            if dapp.owner != caller {
+                return Err(Error::NotAuthorised);
             }
-            self.dapp_deregister(contract);
-            self.env().emit_event(DappCancel {
-                contract,
-                value: balance,
-            });
-
+            self.dapp_provider_deny_lists
+                .insert(contract, &providers.into_iter().collect::<BTreeSet<_>>());
+            self.emit_dapp_provider_list_update(contract);
             Ok(())
         }
 
-        /// De-Register a dapp by setting their status to Deactivated and their balance to 0
-        fn dapp_deregister(&mut self, dapp_account: AccountId) {
-            let mut dapp = self.dapps.get(&dapp_account).unwrap();
-            dapp.status = GovernanceStatus::Deactivated;
-            dapp.balance = 0;
-            self.dapps.insert(dapp_account, &dapp);
-            // TODO should these be retained or not?
-            //self.dapp_accounts.retain(|account: &AccountId| account != &dapp_account);
+        fn emit_dapp_provider_list_update(&self, contract: AccountId) {
+            self.env().emit_event(DappProviderListUpdate {
+                contract,
+                allow_list: self.get_dapp_provider_allow_list(contract),
+                deny_list: self.get_dapp_provider_deny_list(contract),
+            });
         }
 
-        /// Submit a captcha solution commit
+        /// Propose a new owner for a dapp, who must accept ownership before it is transferred
         #[ink(message)]
-        pub fn dapp_user_commit(
+        pub fn dapp_propose_owner(
             &mut self,
             contract: AccountId,
-            captcha_dataset_id: Hash,
-            user_merkle_tree_root: Hash,
-            provider: AccountId,
+            new_owner: AccountId,
         ) -> Result<(), Error> {
             let caller = self.env().caller();
-            // Guard against incorrect data being submitted
-            self.get_captcha_data(captcha_dataset_id)?;
-            // Guard against solution commitment being submitted more than once
-            if self
-                .captcha_solution_commitments
-                .get(user_merkle_tree_root)
-                .is_some()
-            {
-                ink_env::debug_println!("{}", "CaptchaSolutionCommitmentExists");
-                //return Err(Error::CaptchaSolutionCommitmentExists);
-                return Ok(());
+            let dapp = self.get_dapp_details(contract)?;
+            if dapp.owner != caller {
+                return Err(Error::NotAuthorised);
             }
+            self.dapp_set_pending_owner(contract, dapp.owner, new_owner);
+            Ok(())
+        }
+
+        fn dapp_set_pending_owner(
+            &mut self,
+            contract: AccountId,
+            owner: AccountId,
+            pending_owner: AccountId,
+        ) {
+            self.dapp_pending_owners.insert(contract, &pending_owner);
+            self.env().emit_event(DappOwnershipProposed {
+                contract,
+                owner,
+                pending_owner,
+            });
+        }
+
+        /// Accept ownership of a dapp as its proposed owner
+        #[ink(message)]
+        pub fn dapp_accept_ownership(&mut self, contract: AccountId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let mut dapp = self.get_dapp_details(contract)?;
+            let pending_owner = self
+                .dapp_pending_owners
+                .get(contract)
+                .ok_or(Error::DappNoPendingOwner)?;
+            if pending_owner != caller {
+                return Err(Error::NotAuthorised);
+            }
+            let previous_owner = dapp.owner;
+            dapp.owner = pending_owner;
+            self.dapps.insert(contract, &dapp);
+            self.dapp_pending_owners.remove(contract);
+            self.env().emit_event(DappOwnershipTransferred {
+                contract,
+                previous_owner,
+                owner: pending_owner,
+            });
+            Ok(())
+        }
+
+        /// Cancel a proposed ownership transfer, as either the owner or the proposed owner
+        #[ink(message)]
+        pub fn dapp_cancel_ownership_transfer(&mut self, contract: AccountId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let dapp = self.get_dapp_details(contract)?;
+            let pending_owner = self
+                .dapp_pending_owners
+                .get(contract)
+                .ok_or(Error::DappNoPendingOwner)?;
+            if caller != dapp.owner && caller != pending_owner {
+                return Err(Error::NotAuthorised);
+            }
+            self.dapp_pending_owners.remove(contract);
+            self.env().emit_event(DappOwnershipTransferCancelled {
+                contract,
+                pending_owner,
+            });
+            Ok(())
+        }
+
+        /// Verify a dapp registration by calling from the dapp contract itself
+        ///
+        /// The contract nominates the dapp's owner. If the registration was made by a different
+        /// owner, their unreserved funds are returned to them
+        #[ink(message)]
+        pub fn dapp_verify(&mut self, owner: AccountId) -> Result<(), Error> {
+            let contract = self.env().caller();
+            let mut dapp = self.get_dapp_details(contract)?;
+            let previous_owner = dapp.owner;
+            if previous_owner != owner {
+                let refund = dapp.balance - dapp.reserved;
+                if refund > 0 && self.env().transfer(previous_owner, refund).is_err() {
+                    return Err(Error::ContractTransferFailed);
+                }
+                dapp.balance -= refund;
+                dapp.owner = owner;
+                if dapp.status == GovernanceStatus::Active && dapp.balance == 0 {
+                    dapp.status = GovernanceStatus::Suspended;
+                }
+                self.dapp_pending_owners.remove(contract);
+            }
+            dapp.verified = true;
+            self.dapps.insert(contract, &dapp);
+            self.env().emit_event(DappVerify { contract, owner });
+            if previous_owner != owner {
+                self.env().emit_event(DappOwnershipTransferred {
+                    contract,
+                    previous_owner,
+                    owner,
+                });
+            }
+            Ok(())
+        }
+
+        /// Fund dapp account to pay for services, if the Dapp caller is registered in self.dapps
+        #[ink(message)]
+        #[ink(payable)]
+        pub fn dapp_fund(&mut self, contract: AccountId) {
+            let caller = self.env().caller();
+            let transferred = self.env().transferred_value();
+            let dapp = self
+                .dapps
+                .get(&contract)
+                .filter(|dapp| dapp.status != GovernanceStatus::Deactivated);
+            if let Some(mut dapp) = dapp {
+                let total = dapp.balance + transferred;
+                dapp.balance = total;
+                if dapp.balance > 0 {
+                    dapp.status = GovernanceStatus::Active;
+                    self.env().emit_event(DappFund {
+                        contract,
+                        value: total,
+                    });
+                } else {
+                    // Suspended as dapp has no funds
+                    dapp.status = GovernanceStatus::Suspended;
+                }
+                self.dapps.insert(contract, &dapp);
+            } else {
+                //return the transferred balance to the caller as the dapp does not exist or is
+                //deactivated
+                self.env().transfer(caller, transferred).ok();
+            }
+        }
+
+        /// Cancel services as a dapp, returning remaining tokens
+        ///
+        /// Funds reserved for pending commitments are retained until the providers' verdicts settle
+        /// them, after which any remainder can be withdrawn
+        #[ink(message)]
+        pub fn dapp_cancel(&mut self, contract: AccountId) -> Result<(), Error> {
+            let caller = self.env().caller();
+
+            if self.dapps.get(&contract).is_none() {
+                return Err(Error::DappDoesNotExist);
+            }
+            let mut dapp = self.get_dapp_details(contract)?;
+
+            // TODO should the operators be authorised to do this ?
+            // TODO If an owner is not specified then the Dapp contract can never be cancelled
+            if dapp.owner != caller {
+                return Err(Error::NotAuthorised);
+            }
+
+            let balance = dapp.balance - dapp.reserved;
+            if balance > 0 && self.env().transfer(caller, balance).is_err() {
+                return Err(Error::ContractTransferFailed);
+            }
+            dapp.balance = dapp.reserved;
+            self.dapps.insert(contract, &dapp);
+            self.dapp_deregister(contract);
+            self.env().emit_event(DappCancel {
+                contract,
+                value: balance,
+            });
+
+            Ok(())
+        }
+
+        /// Withdraw funds from a dapp's balance
+        ///
+        /// Funds reserved for pending commitments cannot be withdrawn. An active dapp whose balance
+        /// is withdrawn in full is suspended
+        #[ink(message)]
+        pub fn dapp_withdraw(&mut self, contract: AccountId, amount: Balance) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let mut dapp = self.get_dapp_details(contract)?;
+            if dapp.owner != caller {
+                return Err(Error::NotAuthorised);
+            }
+            if amount > dapp.balance - dapp.reserved {
+                return Err(Error::DappInsufficientFunds);
+            }
+            if self.env().transfer(caller, amount).is_err() {
+                return Err(Error::ContractTransferFailed);
+            }
+            dapp.balance -= amount;
+            if dapp.balance == 0 && dapp.status == GovernanceStatus::Active {
+                // Suspended as dapp has no funds
+                dapp.status = GovernanceStatus::Suspended;
+            }
+            self.dapps.insert(contract, &dapp);
+            self.env().emit_event(DappWithdraw {
+                contract,
+                value: amount,
+            });
+            Ok(())
+        }
+
+        /// De-Register a dapp by setting their status to Deactivated
+        fn dapp_deregister(&mut self, dapp_account: AccountId) {
+            let mut dapp = self.dapps.get(&dapp_account).unwrap();
+            dapp.status = GovernanceStatus::Deactivated;
+            self.dapps.insert(dapp_account, &dapp);
+        }
+
+        /// Reactivate a deactivated dapp as its owner, optionally transferring further funds
+        #[ink(message)]
+        #[ink(payable)]
+        pub fn dapp_reactivate(&mut self, contract: AccountId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let transferred = self.env().transferred_value();
+            let mut dapp = self.get_dapp_details(contract)?;
+            if dapp.owner != caller {
+                return Err(Error::NotAuthorised);
+            }
+            if dapp.status != GovernanceStatus::Deactivated {
+                return Err(Error::DappNotDeactivated);
+            }
+            dapp.balance += transferred;
+            // funds reserved for commitments made before deactivation cannot fund new ones
+            dapp.status = if dapp.balance > dapp.reserved {
+                GovernanceStatus::Active
+            } else {
+                GovernanceStatus::Suspended
+            };
+            self.dapps.insert(contract, &dapp);
+            self.env().emit_event(DappReactivate {
+                contract,
+                value: dapp.balance,
+            });
+            Ok(())
+        }
+
+        /// Remove a deactivated dapp, returning any remaining funds to its owner
+        ///
+        /// The dapp must have no commitments awaiting a verdict. Once removed, the contract can be
+        /// registered again from scratch
+        #[ink(message)]
+        pub fn dapp_remove(&mut self, contract: AccountId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let dapp = self.get_dapp_details(contract)?;
+            if dapp.owner != caller {
+                return Err(Error::NotAuthorised);
+            }
+            if dapp.status != GovernanceStatus::Deactivated {
+                return Err(Error::DappNotDeactivated);
+            }
+            if dapp.pending_commitments > 0 {
+                return Err(Error::DappHasPendingCommitments);
+            }
+            if dapp.balance > 0 && self.env().transfer(caller, dapp.balance).is_err() {
+                return Err(Error::ContractTransferFailed);
+            }
+            self.dapps.remove(contract);
+            self.dapp_accounts
+                .retain(|account: &AccountId| account != &contract);
+            self.dapp_pending_owners.remove(contract);
+            self.dapp_provider_allow_lists.remove(contract);
+            self.dapp_provider_deny_lists.remove(contract);
+            self.env().emit_event(DappRemove {
+                contract,
+                value: dapp.balance,
+            });
+            Ok(())
+        }
+
+        /// Submit a captcha solution commit
+        ///
+        /// Resubmitting a commitment the caller has already made leaves it unchanged and returns
+        /// `CommitmentOutcome::AlreadyExists`
+        #[ink(message)]
+        pub fn dapp_user_commit(
+            &mut self,
+            contract: AccountId,
+            captcha_dataset_id: Hash,
+            user_merkle_tree_root: Hash,
+            provider: AccountId,
+        ) -> Result<CommitmentOutcome, Error> {
+            let caller = self.env().caller();
+            // Guard against incorrect data being submitted
+            let dataset = self.get_captcha_data(captcha_dataset_id)?;
+            if dataset.provider != provider {
+                return Err(Error::CaptchaDataProviderMismatch);
+            }
+            if dataset.status != CaptchaDataStatus::Active {
+                return Err(Error::CaptchaDataRetired);
+            }
+            // Guard against solution commitment being submitted more than once
+            if let Some(existing) = self.captcha_solution_commitments.get(user_merkle_tree_root) {
+                if existing.account != caller {
+                    return Err(Error::CaptchaSolutionCommitmentExists);
+                }
+                return Ok(CommitmentOutcome::AlreadyExists);
+            }
+
+            self.validate_dapp(contract)?;
+            self.validate_provider(provider)?;
+            let mut dapp = self.get_dapp_details(contract)?;
+            if !Self::captcha_data_meets_dapp_requirements(&dataset, &dapp) {
+                return Err(Error::CaptchaDataDoesNotMeetDappRequirements);
+            }
+            if !self.dapp_permits_provider(contract, provider) {
+                return Err(Error::ProviderNotPermittedByDapp);
+            }
+
+            // reserve the provider's fee and the user's refund so the dapp cannot withdraw them
+            // before the verdict
+            let provider_details = self.get_provider_details(provider)?;
+            let (reserved, refund_reserved) = if provider_details.payee == Payee::Provider {
+                (self.provider_fee(&provider_details), dapp.refund_reserve)
+            } else {
+                (0, 0)
+            };
+            if reserved + refund_reserved > dapp.balance - dapp.reserved {
+                return Err(Error::DappInsufficientFunds);
+            }
+            dapp.reserved += reserved + refund_reserved;
+            dapp.pending_commitments += 1;
+            self.dapps.insert(contract, &dapp);
 
-            self.validate_dapp(contract)?;
-            self.validate_provider(provider)?;
-
             let commitment = CaptchaSolutionCommitment {
                 account: caller,
                 captcha_dataset_id,
                 status: CaptchaStatus::Pending,
                 contract,
                 provider,
+                created_block: self.env().block_number(),
+                created_timestamp: self.env().block_timestamp(),
+                verdict_block: 0,
+                verdict_timestamp: 0,
+                reserved,
+                refund_reserved,
+                dapp_paid: 0,
+                previous_correct_captcha_id: Hash::default(),
+                previous_dapp_correct_captcha_id: Hash::default(),
+                reveal: if dapp.solution_reveal_required {
+                    SolutionReveal::Required
+                } else {
+                    SolutionReveal::NotRequired
+                },
             };
 
             self.create_new_dapp_user(caller);
+            self.provider_update_stats(provider, |stats| stats.assigned += 1);
 
             self.captcha_solution_commitments
                 .insert(user_merkle_tree_root, &commitment);
@@ -854,18 +2076,18 @@ pub mod prosopo {
                 contract,
                 captcha_dataset_id,
             });
-            Ok(())
+            Ok(CommitmentOutcome::Created)
         }
 
         /// Create a new dapp user if they do not already exist
         fn create_new_dapp_user(&mut self, account: AccountId) {
             // create the user and add to our list of dapp users
             if self.dapp_users.get(account).is_none() {
+                let timestamp = self.env().block_timestamp();
                 let user = User {
-                    correct_captchas: 0,
-                    incorrect_captchas: 0,
-                    //last_correct_captcha: (),
-                    //last_correct_captcha_dapp_id: (),
+                    created: timestamp,
+                    updated: timestamp,
+                    ..Default::default()
                 };
                 self.dapp_users.insert(account, &user);
                 self.dapp_user_accounts.push(account);
@@ -880,40 +2102,11 @@ pub mod prosopo {
             captcha_solution_commitment_id: Hash,
             transaction_fee: Balance,
         ) -> Result<(), Error> {
-            let caller = self.env().caller();
-            self.validate_provider(caller)?;
-            // Guard against incorrect solution id
-            let commitment =
-                self.get_captcha_solution_commitment(captcha_solution_commitment_id)?;
-            if commitment.provider != caller {
-                return Err(Error::NotAuthorised);
-            }
-            self.validate_dapp(commitment.contract)?;
-
-            self.get_dapp_user(commitment.account)?;
-
-            // get the mutables
-            let mut commitment_mut = self
-                .captcha_solution_commitments
-                .get(&captcha_solution_commitment_id)
-                .unwrap();
-            let mut user = self.dapp_users.get(&commitment.account).unwrap();
-
-            // only make changes if commitment is Pending approval or disapproval
-            if commitment_mut.status == CaptchaStatus::Pending {
-                commitment_mut.status = CaptchaStatus::Approved;
-                user.correct_captchas += 1;
-                self.captcha_solution_commitments
-                    .insert(captcha_solution_commitment_id, &commitment_mut);
-                self.dapp_users.insert(&commitment.account, &user);
-                self.pay_fee(&caller, &commitment.contract)?;
-                self.refund_transaction_fee(commitment, transaction_fee)?;
-                self.env().emit_event(ProviderApprove {
-                    captcha_solution_commitment_id,
-                });
-            }
-
-            Ok(())
+            self.provider_give_verdict(
+                captcha_solution_commitment_id,
+                Verdict::Approved,
+                transaction_fee,
+            )
         }
 
         /// Disapprove a solution commitment and increment incorrect captchas
@@ -922,354 +2115,1717 @@ pub mod prosopo {
             &mut self,
             captcha_solution_commitment_id: Hash,
         ) -> Result<(), Error> {
+            self.provider_give_verdict(captcha_solution_commitment_id, Verdict::Disapproved, 0)
+        }
+
+        /// Give a single verdict, settled in the same way as a batch of verdicts
+        fn provider_give_verdict(
+            &mut self,
+            captcha_solution_commitment_id: Hash,
+            verdict: Verdict,
+            transaction_fee: Balance,
+        ) -> Result<(), Error> {
+            let mut results = self.provider_submit_verdicts(vec![(
+                captcha_solution_commitment_id,
+                verdict,
+                transaction_fee,
+            )])?;
+            // a batch of one verdict always has one result
+            match results.pop() {
+                Some(result) => result,
+                None => Err(Error::CaptchaSolutionCommitmentDoesNotExist),
+            }
+        }
+
+        /// Approve or disapprove a batch of solution commitments
+        ///
+        /// The provider is validated once and the fees owed between the provider and each dapp are
+        /// settled in aggregate. Returns the result of each verdict in the order they were given
+        #[ink(message)]
+        pub fn provider_submit_verdicts(
+            &mut self,
+            verdicts: Vec<(Hash, Verdict, Balance)>,
+        ) -> Result<Vec<Result<(), Error>>, Error> {
             let caller = self.env().caller();
             self.validate_provider(caller)?;
-            // Guard against incorrect solution id
-            let commitment =
+            let mut provider = self.get_provider_details(caller)?;
+            let mut stats = self.provider_stats.get(caller).unwrap_or_default();
+            let mut dapps = BTreeMap::<AccountId, Dapp>::new();
+            let results = verdicts
+                .into_iter()
+                .map(|verdict| {
+                    self.provider_apply_verdict(&mut provider, &mut stats, &mut dapps, verdict)
+                })
+                .collect();
+            for (contract, dapp) in dapps {
+                self.dapps.insert(contract, &dapp);
+            }
+            self.providers.insert(caller, &provider);
+            self.provider_stats.insert(caller, &stats);
+            self.provider_reevaluate_status(caller);
+            Ok(results)
+        }
+
+        /// Apply a single verdict of a batch, settling fees against the cached provider and dapps
+        ///
+        /// The cached balances are only updated once the verdict is certain to succeed
+        fn provider_apply_verdict(
+            &mut self,
+            provider: &mut Provider,
+            stats: &mut ProviderStats,
+            dapps: &mut BTreeMap<AccountId, Dapp>,
+            (captcha_solution_commitment_id, verdict, transaction_fee): (Hash, Verdict, Balance),
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let mut commitment =
                 self.get_captcha_solution_commitment(captcha_solution_commitment_id)?;
             if commitment.provider != caller {
                 return Err(Error::NotAuthorised);
             }
-            self.validate_dapp(commitment.contract)?;
-            // Check the user exists
+            if verdict == Verdict::Approved && transaction_fee > self.dapp_refund_max {
+                return Err(Error::TransactionFeeRefundTooHigh);
+            }
+            let mut dapp = match dapps.get(&commitment.contract) {
+                Some(dapp) => dapp.clone(),
+                None => self.get_dapp_details(commitment.contract)?,
+            };
             self.get_dapp_user(commitment.account)?;
-
-            // get the mutables
-            let mut commitment_mut = self
-                .captcha_solution_commitments
-                .get(&captcha_solution_commitment_id)
-                .unwrap();
-            let mut user = self.dapp_users.get(&commitment.account).unwrap();
-
             // only make changes if commitment is Pending approval or disapproval
-            if commitment_mut.status == CaptchaStatus::Pending {
-                commitment_mut.status = CaptchaStatus::Disapproved;
-                user.incorrect_captchas += 1;
-                self.captcha_solution_commitments
-                    .insert(captcha_solution_commitment_id, &commitment_mut);
-                self.dapp_users.insert(&commitment.account, &user);
-                self.pay_fee(&caller, &commitment.contract)?;
-                self.env().emit_event(ProviderDisapprove {
-                    captcha_solution_commitment_id,
-                });
+            match commitment.status {
+                CaptchaStatus::Pending => {}
+                CaptchaStatus::Expired => return Err(Error::CaptchaSolutionCommitmentExpired),
+                CaptchaStatus::Approved | CaptchaStatus::Disapproved => {
+                    return Err(Error::CaptchaSolutionCommitmentNotPending)
+                }
             }
 
-            Ok(())
-        }
-
-        /// Transfer a balance from a provider to a dapp or from a dapp to a provider,
-        fn pay_fee(
-            &mut self,
-            provider_account: &AccountId,
-            dapp_account: &AccountId,
-        ) -> Result<(), Error> {
-            let mut provider = self.providers.get(provider_account).unwrap();
-            if provider.fee != 0 {
-                let mut dapp = self.dapps.get(dapp_account).unwrap();
-
-                let fee = Balance::from(provider.fee);
-                if provider.payee == Payee::Provider {
-                    provider.balance += fee;
-                    dapp.balance -= fee;
+            let refund = if verdict == Verdict::Approved {
+                transaction_fee
+            } else {
+                0
+            };
+            let dapp_balance = dapp.balance;
+            self.settle_verdict(&commitment, provider, &mut dapp, refund)?;
+            commitment.dapp_paid = dapp_balance.saturating_sub(dapp.balance);
+            dapps.insert(commitment.contract, dapp);
+
+            commitment.verdict_block = self.env().block_number();
+            commitment.verdict_timestamp = self.env().block_timestamp();
+            match verdict {
+                Verdict::Approved => {
+                    commitment.status = CaptchaStatus::Approved;
+                    self.dapp_user_update_records(
+                        commitment.account,
+                        commitment.contract,
+                        |user| user.correct_captchas += 1,
+                    );
+                    self.dapp_user_update_last_correct_captcha(
+                        captcha_solution_commitment_id,
+                        &mut commitment,
+                    );
+                    stats.approved += 1;
+                    self.env().emit_event(ProviderApprove {
+                        captcha_solution_commitment_id,
+                    });
                 }
-                if provider.payee == Payee::Dapp {
-                    provider.balance -= fee;
-                    dapp.balance += fee;
+                Verdict::Disapproved => {
+                    commitment.status = CaptchaStatus::Disapproved;
+                    self.dapp_user_update_records(
+                        commitment.account,
+                        commitment.contract,
+                        |user| user.incorrect_captchas += 1,
+                    );
+                    stats.disapproved += 1;
+                    self.env().emit_event(ProviderDisapprove {
+                        captcha_solution_commitment_id,
+                    });
                 }
-                self.providers.insert(*provider_account, &provider);
-                self.dapps.insert(*dapp_account, &dapp);
             }
+            self.captcha_solution_commitments
+                .insert(captcha_solution_commitment_id, &commitment);
             Ok(())
         }
 
-        /// Transfer a refund fee from payer account to user account
-        /// Payee == Provider => Dapp pays solve fee and Dapp pays Dapp User tx fee
-        /// Payee == Dapp => Provider pays solve fee and Provider pays Dapp Use
-        fn refund_transaction_fee(
+        /// Expire a solution commitment that has not received a verdict within the commitment expiry
+        ///
+        /// Can be called by anyone. The dapp's reserved funds are released and the missed verdict is
+        /// recorded against the provider
+        #[ink(message)]
+        pub fn commitment_expire(
             &mut self,
-            commitment: CaptchaSolutionCommitment,
-            amount: Balance,
+            captcha_solution_commitment_id: Hash,
         ) -> Result<(), Error> {
-            if self.env().balance() < amount {
-                return Err(Error::ContractInsufficientFunds);
-            }
-
-            let mut provider = self.providers.get(&commitment.provider).unwrap();
-            let mut dapp = self.dapps.get(&commitment.contract).unwrap();
-            if provider.payee == Payee::Provider {
-                if dapp.balance < amount {
-                    return Err(Error::DappInsufficientFunds);
-                }
-                dapp.balance -= amount;
-                self.dapps.insert(commitment.contract, &dapp);
-            } else {
-                if provider.balance < amount {
-                    return Err(Error::ProviderInsufficientFunds);
-                }
-                provider.balance -= amount;
-                self.providers.insert(commitment.provider, &provider);
+            let mut commitment =
+                self.get_captcha_solution_commitment(captcha_solution_commitment_id)?;
+            if commitment.status != CaptchaStatus::Pending {
+                return Err(Error::CaptchaSolutionCommitmentNotPending);
             }
-            if self.env().transfer(commitment.account, amount).is_err() {
-                return Err(Error::ContractTransferFailed);
+            if self.env().block_number() <= commitment.created_block + self.commitment_expiry {
+                return Err(Error::CaptchaSolutionCommitmentNotExpired);
             }
+            commitment.status = CaptchaStatus::Expired;
+            self.captcha_solution_commitments
+                .insert(captcha_solution_commitment_id, &commitment);
+            self.dapp_release_reservation(&commitment);
+            self.provider_update_stats(commitment.provider, |stats| stats.timed_out += 1);
+            self.env().emit_event(CommitmentExpire {
+                captcha_solution_commitment_id,
+                provider: commitment.provider,
+            });
             Ok(())
         }
 
-        /// Checks if the user is a human (true) as they have a solution rate higher than a % threshold or a bot (false)
-        /// Threshold is decided by the calling user
+        /// Set the transaction fee refund the dapp reserves for each commitment made to it
+        ///
+        /// Refunds up to this amount are guaranteed to be paid even if the dapp is cancelled before
+        /// the verdict. Larger refunds are paid from the dapp's unreserved funds
         #[ink(message)]
-        pub fn dapp_operator_is_human_user(
+        pub fn dapp_set_refund_reserve(
             &mut self,
-            user: AccountId,
-            threshold: u8,
-        ) -> Result<bool, Error> {
-            let user = self.get_dapp_user(user)?;
-            // determine if correct captchas is greater than or equal to threshold
-            Ok(
-                user.correct_captchas / (user.correct_captchas + user.incorrect_captchas) * 100
-                    >= threshold.into(),
-            )
+            contract: AccountId,
+            value: Balance,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let mut dapp = self.get_dapp_details(contract)?;
+            if dapp.owner != caller {
+                return Err(Error::NotAuthorised);
+            }
+            if value > self.dapp_refund_max {
+                return Err(Error::TransactionFeeRefundTooHigh);
+            }
+            dapp.refund_reserve = value;
+            self.dapps.insert(contract, &dapp);
+            self.env()
+                .emit_event(DappRefundReserveUpdate { contract, value });
+            Ok(())
         }
 
-        // Disputes and governance messages
-
-        /// Add an operator
+        /// Set whether the dapp's users must reveal their solutions after committing them
         #[ink(message)]
-        pub fn add_prosopo_operator(&mut self, operator_account: AccountId) {
+        pub fn dapp_set_solution_reveal(
+            &mut self,
+            contract: AccountId,
+            required: bool,
+        ) -> Result<(), Error> {
             let caller = self.env().caller();
-            if self.operators.get(&caller).is_some() {
-                let operator = Operator {
-                    status: GovernanceStatus::Active,
-                };
-                self.operators.insert(operator_account, &operator);
-                self.operator_accounts.push(operator_account);
+            let mut dapp = self.get_dapp_details(contract)?;
+            if dapp.owner != caller {
+                return Err(Error::NotAuthorised);
             }
+            dapp.solution_reveal_required = required;
+            self.dapps.insert(contract, &dapp);
+            self.env()
+                .emit_event(DappSolutionRevealUpdate { contract, required });
+            Ok(())
         }
 
-        // Informational / Validation functions
-
-        fn validate_provider(&self, provider_id: AccountId) -> Result<(), Error> {
-            if self.providers.get(&provider_id).is_none() {
-                ink_env::debug_println!("{}", "ProviderDoesNotExist");
-                return Err(Error::ProviderDoesNotExist);
+        /// Reveal the solutions of a commitment, as the dapp user or the provider on their behalf
+        ///
+        /// `solutions` are the `(captcha_id, solution)` pairs of the user's tree, in leaf order. The
+        /// root of the tree salted with `salt` must be the committed root
+        #[ink(message)]
+        pub fn commitment_reveal(
+            &mut self,
+            captcha_solution_commitment_id: Hash,
+            solutions: Vec<(Hash, Hash)>,
+            salt: Hash,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let mut commitment =
+                self.get_captcha_solution_commitment(captcha_solution_commitment_id)?;
+            if caller != commitment.account && caller != commitment.provider {
+                return Err(Error::NotAuthorised);
             }
-            let provider = self.get_provider_details(provider_id)?;
-            if provider.status != GovernanceStatus::Active {
-                ink_env::debug_println!("{}", "ProviderInactive");
-                return Err(Error::ProviderInactive);
+            if commitment.reveal != SolutionReveal::Required {
+                return Err(Error::SolutionRevealNotRequired);
             }
-            if provider.balance <= 0 {
-                ink_env::debug_println!("{}", "ProviderInsufficientFunds");
-                return Err(Error::ProviderInsufficientFunds);
+            if self.env().block_number() > commitment.created_block + self.reveal_window {
+                return Err(Error::SolutionRevealWindowClosed);
             }
+            let leaves = solutions
+                .iter()
+                .map(|(captcha_id, solution)| Self::merkle_leaf(captcha_id, solution))
+                .collect();
+            let root = Self::merkle_root(leaves);
+            if Self::merkle_salted_root(&root, &salt) != captcha_solution_commitment_id {
+                return Err(Error::InvalidSolutionReveal);
+            }
+            commitment.reveal = SolutionReveal::Revealed;
+            self.captcha_solution_commitments
+                .insert(captcha_solution_commitment_id, &commitment);
+            self.commitment_solutions
+                .insert(captcha_solution_commitment_id, &solutions);
+            self.env().emit_event(SolutionRevealed {
+                captcha_solution_commitment_id,
+            });
             Ok(())
         }
 
-        fn validate_dapp(&self, contract: AccountId) -> Result<(), Error> {
-            // Guard against dapps using service that are not registered
-            if self.dapps.get(&contract).is_none() {
-                ink_env::debug_println!("{}", "DappDoesNotExist");
-                return Err(Error::DappDoesNotExist);
+        /// Fail a solution commitment whose solutions were not revealed within the reveal window
+        ///
+        /// Can be called by anyone. A pending or approved commitment is disapproved, and the provider
+        /// of an approved one repays the dapp for its fee and the user's refund. A commitment cannot
+        /// be failed while a dispute of its verdict is open, nor disputed once it is failed
+        #[ink(message)]
+        pub fn commitment_finalise_unrevealed(
+            &mut self,
+            captcha_solution_commitment_id: Hash,
+        ) -> Result<(), Error> {
+            let mut commitment =
+                self.get_captcha_solution_commitment(captcha_solution_commitment_id)?;
+            if commitment.reveal != SolutionReveal::Required {
+                return Err(Error::SolutionRevealNotRequired);
             }
-            // Guard against dapps using service that are Suspended or Deactivated
-            let dapp = self.get_dapp_details(contract)?;
-            if dapp.status != GovernanceStatus::Active {
-                ink_env::debug_println!("{}", "DappInactive");
-                return Err(Error::DappInactive);
+            if self.env().block_number() <= commitment.created_block + self.reveal_window {
+                return Err(Error::SolutionRevealWindowOpen);
             }
-            // Make sure the Dapp can pay the transaction fees of the user and potentially the
-            // provider, if their fee > 0
-            if dapp.balance <= 0 {
-                ink_env::debug_println!("{}", "DappInsufficientFunds");
-                return Err(Error::DappInsufficientFunds);
+            let dispute_open = self
+                .commitment_disputes
+                .get(captcha_solution_commitment_id)
+                .and_then(|dispute_id| self.disputes.get(dispute_id))
+                .map_or(false, |dispute| dispute.status == DisputeStatus::Open);
+            if dispute_open {
+                return Err(Error::DisputeExists);
             }
+            match commitment.status {
+                CaptchaStatus::Pending => {
+                    commitment.status = CaptchaStatus::Disapproved;
+                    commitment.verdict_block = self.env().block_number();
+                    commitment.verdict_timestamp = self.env().block_timestamp();
+                    self.dapp_user_update_records(
+                        commitment.account,
+                        commitment.contract,
+                        |user| user.incorrect_captchas += 1,
+                    );
+                    self.dapp_release_reservation(&commitment);
+                }
+                CaptchaStatus::Approved => {
+                    // the provider approved solutions that were never revealed, so it repays the
+                    // dapp what the approval cost it
+                    self.provider_repay_dapp(&commitment);
+                    self.dispute_overturn_verdict(captcha_solution_commitment_id, commitment);
+                    commitment.status = CaptchaStatus::Disapproved;
+                    commitment.dapp_paid = 0;
+                }
+                CaptchaStatus::Disapproved | CaptchaStatus::Expired => {}
+            }
+            commitment.reveal = SolutionReveal::Missed;
+            self.captcha_solution_commitments
+                .insert(captcha_solution_commitment_id, &commitment);
+            self.env().emit_event(SolutionRevealMissed {
+                captcha_solution_commitment_id,
+            });
             Ok(())
         }
 
-        /// Get a single captcha dataset
-        ///
-        /// Returns an error if the dapp does not exist
+        /// Repay the dapp what it paid for a commitment's verdict, as far as the provider's balance
+        /// covers it
+        fn provider_repay_dapp(&mut self, commitment: &CaptchaSolutionCommitment) {
+            let (mut provider, mut dapp) = match (
+                self.providers.get(commitment.provider),
+                self.dapps.get(commitment.contract),
+            ) {
+                (Some(provider), Some(dapp)) => (provider, dapp),
+                _ => return,
+            };
+            let value = core::cmp::min(commitment.dapp_paid, provider.balance);
+            provider.balance -= value;
+            dapp.balance = dapp.balance.saturating_add(value);
+            self.providers.insert(commitment.provider, &provider);
+            self.dapps.insert(commitment.contract, &dapp);
+            self.provider_reevaluate_status(commitment.provider);
+        }
+
+        /// Get the solutions revealed for a solution commitment
         #[ink(message)]
-        pub fn get_captcha_data(&self, captcha_dataset_id: Hash) -> Result<CaptchaData, Error> {
-            if self.captcha_data.get(&captcha_dataset_id).is_none() {
-                ink_env::debug_println!("{}", "CaptchaDatasetDoesNotExist");
-                return Err(Error::CaptchaDataDoesNotExist);
-            }
-            let captcha_data = self.captcha_data.get(&captcha_dataset_id);
-            Ok(captcha_data.unwrap())
+        pub fn get_commitment_solutions(
+            &self,
+            captcha_solution_commitment_id: Hash,
+        ) -> Vec<(Hash, Hash)> {
+            self.commitment_solutions
+                .get(captcha_solution_commitment_id)
+                .unwrap_or_default()
         }
 
-        /// Get a solution commitment
+        /// Verify a user's revealed captcha solutions against their solution commitment
         ///
-        /// Returns an error if the commitment does not exist
+        /// Every solution must be proven to be part of the user's tree, whose root salted with
+        /// `salt` is the committed root. Returns whether each solution is also part of the
+        /// commitment's captcha dataset, i.e. whether it is correct
         #[ink(message)]
-        pub fn get_captcha_solution_commitment(
+        pub fn verify_captcha_solutions(
             &self,
             captcha_solution_commitment_id: Hash,
-        ) -> Result<CaptchaSolutionCommitment, Error> {
-            if self
-                .captcha_solution_commitments
-                .get(&captcha_solution_commitment_id)
-                .is_none()
-            {
-                return Err(Error::CaptchaSolutionCommitmentDoesNotExist);
-            }
-            let commitment = self
-                .captcha_solution_commitments
-                .get(&captcha_solution_commitment_id)
-                .unwrap();
+            salt: Hash,
+            proofs: Vec<CaptchaSolutionProof>,
+        ) -> Result<Vec<bool>, Error> {
+            let commitment =
+                self.get_captcha_solution_commitment(captcha_solution_commitment_id)?;
+            let dataset = self.get_captcha_data(commitment.captcha_dataset_id)?;
+            proofs
+                .iter()
+                .map(|proof| {
+                    let leaf = Self::merkle_leaf(&proof.captcha_id, &proof.solution);
+                    let committed = Self::merkle_proof_root(leaf, &proof.commitment_proof)
+                        .map(|root| Self::merkle_salted_root(&root, &salt));
+                    if committed != Some(captcha_solution_commitment_id) {
+                        return Err(Error::InvalidSolutionCommitmentProof);
+                    }
+                    Ok(Self::merkle_proof_verifies(
+                        leaf,
+                        &proof.dataset_proof,
+                        dataset.merkle_tree_root,
+                    ))
+                })
+                .collect()
+        }
 
-            Ok(commitment)
+        /// Hash a captcha and its solution into a merkle tree leaf
+        fn merkle_leaf(captcha_id: &Hash, solution: &Hash) -> Hash {
+            Self::merkle_hash(MERKLE_LEAF_PREFIX, captcha_id, solution)
         }
 
-        /// Get a dapp user
-        ///
-        /// Returns an error if the user does not exist
-        #[ink(message)]
-        pub fn get_dapp_user(&self, dapp_user_id: AccountId) -> Result<User, Error> {
-            if self.dapp_users.get(&dapp_user_id).is_none() {
-                ink_env::debug_println!("{}", "DappUserDoesNotExist");
-                return Err(Error::DappUserDoesNotExist);
-            }
-            Ok(self.dapp_users.get(&dapp_user_id).unwrap())
+        /// Hash the concatenation of two merkle tree nodes
+        fn merkle_hash_pair(left: &Hash, right: &Hash) -> Hash {
+            Self::merkle_hash(MERKLE_NODE_PREFIX, left, right)
         }
 
-        /// Get a single provider's details
-        ///
-        /// Returns an error if the user does not exist
-        #[ink(message)]
-        pub fn get_provider_details(&self, accountid: AccountId) -> Result<Provider, Error> {
-            if self.providers.get(&accountid).is_none() {
-                ink_env::debug_println!("{}", "ProviderDoesNotExist");
-                return Err(Error::ProviderDoesNotExist);
-            }
-            let provider = self.providers.get(&accountid);
-            Ok(provider.unwrap())
+        /// Salt the root of a user's solution tree to form the root they commit to
+        fn merkle_salted_root(root: &Hash, salt: &Hash) -> Hash {
+            Self::merkle_hash(MERKLE_SALTED_ROOT_PREFIX, root, salt)
         }
 
-        /// Get a single dapps details
+        /// Hash the concatenation of `prefix`, `left` and `right`
+        fn merkle_hash(prefix: u8, left: &Hash, right: &Hash) -> Hash {
+            let mut input = [0u8; 65];
+            input[0] = prefix;
+            input[1..33].copy_from_slice(left.as_ref());
+            input[33..].copy_from_slice(right.as_ref());
+            let mut output =
+                <ink_env::hash::Blake2x256 as ink_env::hash::HashOutput>::Type::default();
+            ink_env::hash_bytes::<ink_env::hash::Blake2x256>(&input, &mut output);
+            Hash::from(output)
+        }
+
+        /// Compute the root of the merkle tree with the given leaves
         ///
-        /// Returns an error if the dapp does not exist
-        #[ink(message)]
-        pub fn get_dapp_details(&self, contract: AccountId) -> Result<Dapp, Error> {
-            if self.dapps.get(&contract).is_none() {
-                ink_env::debug_println!("{}", "DappDoesNotExist");
-                return Err(Error::DappDoesNotExist);
+        /// A node without a sibling is promoted to the next layer unchanged, so a tree cannot
+        /// share its root with one that repeats its last leaf
+        fn merkle_root(mut nodes: Vec<Hash>) -> Hash {
+            if nodes.is_empty() {
+                return Hash::default();
             }
-            let dapp = self.dapps.get(&contract);
-            Ok(dapp.unwrap())
+            while nodes.len() > 1 {
+                nodes = nodes
+                    .chunks(2)
+                    .map(|pair| match pair {
+                        [left, right] => Self::merkle_hash_pair(left, right),
+                        _ => pair[0],
+                    })
+                    .collect();
+            }
+            nodes[0]
         }
 
-        /// Returns the account balance for the specified `dapp`.
-        ///
-        /// Returns `0` if the account does not exist.
-        #[ink(message)]
-        pub fn get_dapp_balance(&self, dapp: AccountId) -> Balance {
-            return match self.get_dapp_details(dapp) {
-                Ok(v) => v.balance,
-                Err(_e) => Balance::from(0_u32),
-            };
+        /// Check that `leaf` is part of the tree with root `root`
+        fn merkle_proof_verifies(leaf: Hash, proof: &[[Hash; 2]], root: Hash) -> bool {
+            Self::merkle_proof_root(leaf, proof) == Some(root)
         }
 
-        /// Returns the account balance for the specified `provider`.
+        /// Compute the root of the tree `proof` proves `leaf` is part of
         ///
-        /// Returns `0` if the account does not exist.
-        #[ink(message)]
-        pub fn get_provider_balance(&self, provider: AccountId) -> Balance {
-            return match self.get_provider_details(provider) {
-                Ok(v) => v.balance,
-                Err(_e) => Balance::from(0_u32),
+        /// Returns None if a layer of the proof does not contain the node below it
+        fn merkle_proof_root(leaf: Hash, proof: &[[Hash; 2]]) -> Option<Hash> {
+            let mut node = leaf;
+            for [left, right] in proof {
+                if *left != node && *right != node {
+                    return None;
+                }
+                node = Self::merkle_hash_pair(left, right);
+            }
+            Some(node)
+        }
+
+        /// Apply `update` to the dapp user's global record and their record for the dapp
+        fn dapp_user_update_records<F>(
+            &mut self,
+            account: AccountId,
+            contract: AccountId,
+            update: F,
+        ) where
+            F: Fn(&mut User),
+        {
+            let timestamp = self.env().block_timestamp();
+            let new_record = User {
+                created: timestamp,
+                ..Default::default()
             };
+            let mut user = self.dapp_users.get(account).unwrap_or(new_record);
+            update(&mut user);
+            user.updated = timestamp;
+            self.dapp_users.insert(account, &user);
+            let mut dapp_record = self
+                .dapp_user_dapp_records
+                .get((contract, account))
+                .unwrap_or(new_record);
+            update(&mut dapp_record);
+            dapp_record.updated = timestamp;
+            self.dapp_user_dapp_records
+                .insert((contract, account), &dapp_record);
         }
 
-        /// List providers given an array of account id
+        /// Add a commitment that has been approved to the dapp user's chains of approved
+        /// commitments, or take one that has been disapproved out of them, and point the user's
+        /// records at their most recent approval that still stands
         ///
-        /// Returns empty if none were matched
-        #[ink(message)]
-        pub fn list_providers_by_ids(&self, provider_ids: Vec<AccountId>) -> Vec<Provider> {
-            let mut providers = Vec::new();
-            for provider_id in provider_ids {
-                let provider = self.providers.get(provider_id);
-                if provider.is_none() {
-                    continue;
+        /// The chains are ordered by verdict block, across all dapps for the user's global record
+        /// and for the commitment's dapp for their record for the dapp. The commitment must be
+        /// stored by the caller afterwards
+        fn dapp_user_update_last_correct_captcha(
+            &mut self,
+            captcha_solution_commitment_id: Hash,
+            commitment: &mut CaptchaSolutionCommitment,
+        ) {
+            let key = (commitment.contract, commitment.account);
+            for for_dapp in [false, true] {
+                let mut user = if for_dapp {
+                    self.dapp_user_dapp_records.get(key)
+                } else {
+                    self.dapp_users.get(commitment.account)
+                }
+                .unwrap_or_default();
+                let last = user.last_correct_captcha_id;
+                user.last_correct_captcha_id = if commitment.status == CaptchaStatus::Approved {
+                    self.correct_captcha_chain_insert(
+                        last,
+                        captcha_solution_commitment_id,
+                        commitment,
+                        for_dapp,
+                    )
+                } else if last == captcha_solution_commitment_id {
+                    self.correct_captcha_chain_previous(commitment, for_dapp)
+                } else {
+                    last
+                };
+                if for_dapp {
+                    self.dapp_user_dapp_records.insert(key, &user);
+                } else {
+                    self.dapp_users.insert(commitment.account, &user);
                 }
-                providers.push(provider.unwrap())
             }
-            providers
         }
 
-        /// List providers given an array of status
-        ///
-        /// Returns empty if none were matched
-        #[ink(message)]
-        pub fn list_providers_by_status(&self, statuses: Vec<GovernanceStatus>) -> Vec<Provider> {
-            let mut providers = Vec::<Provider>::new();
-            for status in statuses {
-                let providers_set = self.provider_accounts.get(status);
-                if providers_set.is_none() {
-                    continue;
+        /// Insert an approved commitment into the chain of approvals starting at `last` in order of
+        /// verdict block, returning the new start of the chain
+        fn correct_captcha_chain_insert(
+            &mut self,
+            last: Hash,
+            captcha_solution_commitment_id: Hash,
+            commitment: &mut CaptchaSolutionCommitment,
+            for_dapp: bool,
+        ) -> Hash {
+            let mut later = None;
+            let mut current = last;
+            while let Some(current_commitment) = self.get_correct_captcha_commitment(current) {
+                if current_commitment.verdict_block <= commitment.verdict_block {
+                    break;
                 }
-                let provider_ids = providers_set.unwrap().into_iter().collect();
-                providers.append(&mut self.list_providers_by_ids(provider_ids));
+                later = Some((current, current_commitment));
+                current = Self::previous_correct_captcha_id(&current_commitment, for_dapp);
+            }
+            Self::set_previous_correct_captcha_id(commitment, for_dapp, current);
+            match later {
+                Some((later_id, mut later_commitment)) => {
+                    Self::set_previous_correct_captcha_id(
+                        &mut later_commitment,
+                        for_dapp,
+                        captcha_solution_commitment_id,
+                    );
+                    self.captcha_solution_commitments
+                        .insert(later_id, &later_commitment);
+                    last
+                }
+                None => captcha_solution_commitment_id,
             }
-            providers
         }
 
-        /// Get a random active provider
-        ///
-        /// Returns error if no active providers is found
-        #[ink(message)]
-        pub fn get_random_active_provider(
+        /// Find the most recent approval that still stands in the chain preceding `commitment`
+        fn correct_captcha_chain_previous(
             &self,
-            user_account: AccountId,
-        ) -> Result<RandomProvider, Error> {
-            let active_providers = self
-                .provider_accounts
-                .get(GovernanceStatus::Active)
-                .unwrap();
-            let max = active_providers.len();
-            if max == 0 {
-                return Err(Error::NoActiveProviders);
+            commitment: &CaptchaSolutionCommitment,
+            for_dapp: bool,
+        ) -> Hash {
+            let mut current = Self::previous_correct_captcha_id(commitment, for_dapp);
+            while let Some(current_commitment) = self.get_correct_captcha_commitment(current) {
+                if current_commitment.status == CaptchaStatus::Approved {
+                    break;
+                }
+                current = Self::previous_correct_captcha_id(&current_commitment, for_dapp);
             }
-            let index = self.get_random_number(0, (max - 1) as u64, user_account);
-            let provider_id = active_providers.into_iter().nth(index as usize).unwrap();
-            Ok(RandomProvider {
-                provider: self.providers.get(provider_id).unwrap(),
-                block_number: self.env().block_number(),
-            })
+            current
         }
 
-        /// Get the AccountIds of all Providers ever registered
-        ///
-        /// Returns {Vec<AccountId>}
-        #[ink(message)]
-        pub fn get_all_provider_ids(&self) -> Vec<AccountId> {
-            let mut provider_ids = Vec::<AccountId>::new();
-            for status in [
-                GovernanceStatus::Active,
-                GovernanceStatus::Suspended,
-                GovernanceStatus::Deactivated,
-            ] {
-                let providers_set = self.provider_accounts.get(status);
-                if providers_set.is_none() {
-                    continue;
-                }
-                provider_ids.append(&mut providers_set.unwrap().into_iter().collect());
+        /// Get a commitment of a chain of approvals, the default id ends the chain
+        fn get_correct_captcha_commitment(&self, id: Hash) -> Option<CaptchaSolutionCommitment> {
+            if id == Hash::default() {
+                return None;
             }
-            provider_ids
+            self.captcha_solution_commitments.get(id)
         }
 
-        fn get_random_number(&self, min: u64, max: u64, user_account: AccountId) -> u64 {
-            let random_seed = self.env().random(user_account.as_ref());
-            let mut seed_converted: [u8; 32] = Default::default();
-            seed_converted.copy_from_slice(random_seed.0.as_ref());
-            let mut rng = ChaChaRng::from_seed(seed_converted);
-            ((rng.next_u64() / u64::MAX) * (max - min) + min) as u64
+        fn previous_correct_captcha_id(
+            commitment: &CaptchaSolutionCommitment,
+            for_dapp: bool,
+        ) -> Hash {
+            if for_dapp {
+                commitment.previous_dapp_correct_captcha_id
+            } else {
+                commitment.previous_correct_captcha_id
+            }
         }
-    }
 
-    /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
-    /// module and test functions are marked with a `#[test]` attribute.
+        fn set_previous_correct_captcha_id(
+            commitment: &mut CaptchaSolutionCommitment,
+            for_dapp: bool,
+            previous: Hash,
+        ) {
+            if for_dapp {
+                commitment.previous_dapp_correct_captcha_id = previous;
+            } else {
+                commitment.previous_correct_captcha_id = previous;
+            }
+        }
+
+        /// Apply `update` to the provider's performance record
+        fn provider_update_stats<F>(&mut self, provider_account: AccountId, update: F)
+        where
+            F: FnOnce(&mut ProviderStats),
+        {
+            let mut stats = self
+                .provider_stats
+                .get(provider_account)
+                .unwrap_or_default();
+            update(&mut stats);
+            self.provider_stats.insert(provider_account, &stats);
+        }
+
+        /// Release the dapp funds reserved for a commitment that is no longer pending
+        fn dapp_release_reservation(&mut self, commitment: &CaptchaSolutionCommitment) {
+            if let Some(mut dapp) = self.dapps.get(commitment.contract) {
+                Self::dapp_release_reserved(&mut dapp, commitment);
+                self.dapps.insert(commitment.contract, &dapp);
+            }
+        }
+
+        /// Release the funds a dapp reserved for a commitment's fee and refund
+        fn dapp_release_reserved(dapp: &mut Dapp, commitment: &CaptchaSolutionCommitment) {
+            dapp.reserved = dapp.reserved.saturating_sub(
+                commitment
+                    .reserved
+                    .saturating_add(commitment.refund_reserved),
+            );
+            dapp.pending_commitments = dapp.pending_commitments.saturating_sub(1);
+        }
+
+        /// The fee a provider is paid per solution, capped at the protocol's maximum fee
+        fn provider_fee(&self, provider: &Provider) -> Balance {
+            Balance::from(core::cmp::min(provider.fee, self.provider_fee_max))
+        }
+
+        /// Release the dapp's reservation for a commitment, settle the provider's fee and refund
+        /// the user's transaction fee against the given provider and dapp
+        ///
+        /// The refund is transferred last, so the provider and dapp are only changed if this
+        /// succeeds
+        fn settle_verdict(
+            &self,
+            commitment: &CaptchaSolutionCommitment,
+            provider: &mut Provider,
+            dapp: &mut Dapp,
+            transaction_fee: Balance,
+        ) -> Result<(), Error> {
+            let mut provider_after = *provider;
+            let mut dapp_after = dapp.clone();
+            Self::dapp_release_reserved(&mut dapp_after, commitment);
+            self.settle_fee(commitment, &mut provider_after, &mut dapp_after)?;
+            if transaction_fee > 0 {
+                self.refund_transaction_fee(&mut provider_after, &mut dapp_after, transaction_fee)?;
+                if self
+                    .env()
+                    .transfer(commitment.account, transaction_fee)
+                    .is_err()
+                {
+                    return Err(Error::ContractTransferFailed);
+                }
+            }
+            *provider = provider_after;
+            *dapp = dapp_after;
+            Ok(())
+        }
+
+        /// Settle the fee for a commitment's verdict between the provider and the dapp
+        ///
+        /// A dapp pays no more than the fee reserved when the commitment was made, so later
+        /// changes to the provider's fee or payee cannot charge it more than it set aside
+        fn settle_fee(
+            &self,
+            commitment: &CaptchaSolutionCommitment,
+            provider: &mut Provider,
+            dapp: &mut Dapp,
+        ) -> Result<(), Error> {
+            let fee = self.provider_fee(provider);
+            match provider.payee {
+                Payee::Provider => {
+                    let fee = core::cmp::min(fee, commitment.reserved);
+                    dapp.balance = dapp
+                        .balance
+                        .checked_sub(fee)
+                        .ok_or(Error::DappInsufficientFunds)?;
+                    provider.balance = provider.balance.saturating_add(fee);
+                }
+                Payee::Dapp => {
+                    provider.balance = provider
+                        .balance
+                        .checked_sub(fee)
+                        .ok_or(Error::ProviderInsufficientFunds)?;
+                    dapp.balance = dapp.balance.saturating_add(fee);
+                }
+                Payee::None => {}
+            }
+            Ok(())
+        }
+
+        /// Deduct a refund fee from the payer's balance, to be transferred to the user
+        /// Payee == Provider => Dapp pays solve fee and Dapp pays Dapp User tx fee
+        /// Payee == Dapp => Provider pays solve fee and Provider pays Dapp Use
+        fn refund_transaction_fee(
+            &self,
+            provider: &mut Provider,
+            dapp: &mut Dapp,
+            amount: Balance,
+        ) -> Result<(), Error> {
+            if self.env().balance() < amount {
+                return Err(Error::ContractInsufficientFunds);
+            }
+            if provider.payee == Payee::Provider {
+                if dapp.balance.saturating_sub(dapp.reserved) < amount {
+                    return Err(Error::DappInsufficientFunds);
+                }
+                dapp.balance -= amount;
+            } else {
+                provider.balance = provider
+                    .balance
+                    .checked_sub(amount)
+                    .ok_or(Error::ProviderInsufficientFunds)?;
+            }
+            Ok(())
+        }
+
+        /// Set the policy dapp users must meet to be considered human by the dapp
+        #[ink(message)]
+        pub fn dapp_set_human_policy(
+            &mut self,
+            contract: AccountId,
+            policy: HumanPolicy,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let mut dapp = self.get_dapp_details(contract)?;
+            if dapp.owner != caller {
+                return Err(Error::NotAuthorised);
+            }
+            if policy.min_correct_bps > 10_000 {
+                return Err(Error::InvalidHumanPolicy);
+            }
+            dapp.human_policy = policy;
+            self.dapps.insert(contract, &dapp);
+            self.env()
+                .emit_event(DappHumanPolicyUpdate { contract, policy });
+            Ok(())
+        }
+
+        /// Checks if the user is a human (true) or a bot (false) according to the dapp's human
+        /// verification policy
+        ///
+        /// Only the user's solutions for the dapp are taken into account. Returns an error if the
+        /// dapp's registration has not been verified, as its policy is not the dapp's own
+        #[ink(message)]
+        pub fn dapp_is_human(&self, dapp: AccountId, user: AccountId) -> Result<bool, Error> {
+            let details = self.get_dapp_details(dapp)?;
+            if !details.verified {
+                return Err(Error::DappNotVerified);
+            }
+            let policy = details.human_policy;
+            let record = self
+                .dapp_user_dapp_records
+                .get((dapp, user))
+                .unwrap_or_default();
+            let total = record.correct_captchas + record.incorrect_captchas;
+            if total < policy.min_captchas {
+                return Ok(false);
+            }
+            let correct_bps = if total == 0 {
+                0
+            } else {
+                record.correct_captchas * 10_000 / total
+            };
+            if correct_bps < u64::from(policy.min_correct_bps) {
+                return Ok(false);
+            }
+            if policy.max_last_correct_age > 0 {
+                let last_correct =
+                    self.get_correct_captcha_commitment(record.last_correct_captcha_id);
+                let recent = last_correct.map_or(false, |commitment| {
+                    self.env().block_number() - commitment.verdict_block
+                        <= policy.max_last_correct_age
+                });
+                if !recent {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+
+        /// Checks if the user is a human (true) as they have a solution rate higher than a % threshold or a bot (false)
+        /// Threshold is decided by the calling user
+        #[ink(message)]
+        pub fn dapp_operator_is_human_user(
+            &mut self,
+            user: AccountId,
+            threshold: u8,
+        ) -> Result<bool, Error> {
+            let user = self.get_dapp_user(user)?;
+            // determine if correct captchas is greater than or equal to threshold
+            Ok(
+                user.correct_captchas / (user.correct_captchas + user.incorrect_captchas) * 100
+                    >= threshold.into(),
+            )
+        }
+
+        // Disputes and governance messages
+
+        /// Add an operator
+        #[ink(message)]
+        pub fn add_prosopo_operator(&mut self, operator_account: AccountId) {
+            let caller = self.env().caller();
+            if self.operators.get(&caller).is_some() {
+                let operator = Operator {
+                    status: GovernanceStatus::Active,
+                };
+                self.operators.insert(operator_account, &operator);
+                self.operator_accounts.push(operator_account);
+            }
+        }
+
+        /// Dispute a provider's verdict on a solution commitment
+        ///
+        /// Can be opened by the dapp user or the dapp owner within the dispute window
+        #[ink(message)]
+        pub fn dispute_open(&mut self, captcha_solution_commitment_id: Hash) -> Result<u64, Error> {
+            let caller = self.env().caller();
+            let commitment =
+                self.get_captcha_solution_commitment(captcha_solution_commitment_id)?;
+            let dapp = self.get_dapp_details(commitment.contract)?;
+            if caller != commitment.account && caller != dapp.owner {
+                return Err(Error::NotAuthorised);
+            }
+            if commitment.status == CaptchaStatus::Pending {
+                return Err(Error::CaptchaSolutionCommitmentPending);
+            }
+            if commitment.status == CaptchaStatus::Expired {
+                return Err(Error::CaptchaSolutionCommitmentExpired);
+            }
+            // the commitment was failed by the missed reveal rather than the provider's verdict
+            if commitment.reveal == SolutionReveal::Missed {
+                return Err(Error::SolutionRevealMissed);
+            }
+            if self
+                .commitment_disputes
+                .get(captcha_solution_commitment_id)
+                .is_some()
+            {
+                return Err(Error::DisputeExists);
+            }
+            let block_number = self.env().block_number();
+            if block_number > commitment.verdict_block + self.dispute_window {
+                return Err(Error::DisputeWindowClosed);
+            }
+
+            let dispute_id = self.dispute_count;
+            let dispute = Dispute {
+                captcha_solution_commitment_id,
+                claimant: caller,
+                status: DisputeStatus::Open,
+                opened_block: block_number,
+                votes_uphold: 0,
+                votes_reject: 0,
+            };
+            self.disputes.insert(dispute_id, &dispute);
+            self.commitment_disputes
+                .insert(captcha_solution_commitment_id, &dispute_id);
+            self.dispute_count += 1;
+            self.provider_update_stats(commitment.provider, |stats| stats.disputed += 1);
+
+            self.env().emit_event(DisputeOpen {
+                dispute_id,
+                captcha_solution_commitment_id,
+                claimant: caller,
+            });
+            Ok(dispute_id)
+        }
+
+        /// Vote to uphold (overturn the provider's verdict) or reject a dispute
+        ///
+        /// The dispute is resolved once a majority of active operators agree
+        #[ink(message)]
+        pub fn dispute_vote(&mut self, dispute_id: u64, uphold: bool) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.validate_operator(caller)?;
+            let mut dispute = self.get_dispute(dispute_id)?;
+            if dispute.status != DisputeStatus::Open {
+                return Err(Error::DisputeClosed);
+            }
+            if self.env().block_number() > dispute.opened_block + self.dispute_voting_period {
+                return Err(Error::DisputeVotingPeriodEnded);
+            }
+            if self.dispute_votes.get((dispute_id, caller)).is_some() {
+                return Err(Error::DisputeAlreadyVoted);
+            }
+            if uphold {
+                dispute.votes_uphold += 1;
+            } else {
+                dispute.votes_reject += 1;
+            }
+
+            // the vote is only recorded once the dispute it decides has been resolved
+            let active_operators = self.get_active_operator_count();
+            if dispute.votes_uphold * 2 > active_operators {
+                self.dispute_resolve(dispute_id, dispute, DisputeStatus::Upheld)?;
+            } else if dispute.votes_reject * 2 > active_operators {
+                self.dispute_resolve(dispute_id, dispute, DisputeStatus::Rejected)?;
+            } else {
+                self.disputes.insert(dispute_id, &dispute);
+            }
+            self.dispute_votes.insert((dispute_id, caller), &uphold);
+            self.env().emit_event(DisputeVote {
+                dispute_id,
+                operator: caller,
+                uphold,
+            });
+            Ok(())
+        }
+
+        /// Close a dispute that has not reached a majority by the end of its voting period
+        ///
+        /// Can be called by anyone. The dispute is upheld if more operators voted to uphold it than
+        /// to reject it, otherwise the provider's verdict stands
+        #[ink(message)]
+        pub fn dispute_close(&mut self, dispute_id: u64) -> Result<(), Error> {
+            let dispute = self.get_dispute(dispute_id)?;
+            if dispute.status != DisputeStatus::Open {
+                return Err(Error::DisputeClosed);
+            }
+            if self.env().block_number() <= dispute.opened_block + self.dispute_voting_period {
+                return Err(Error::DisputeVotingPeriodOpen);
+            }
+            let status = if dispute.votes_uphold > dispute.votes_reject {
+                DisputeStatus::Upheld
+            } else {
+                DisputeStatus::Rejected
+            };
+            self.dispute_resolve(dispute_id, dispute, status)
+        }
+
+        /// Resolve a dispute, overturning the verdict and slashing the provider if it is upheld
+        ///
+        /// The provider is slashed before anything else is written, so if paying the claimant
+        /// fails the dispute is left open and unchanged
+        fn dispute_resolve(
+            &mut self,
+            dispute_id: u64,
+            mut dispute: Dispute,
+            status: DisputeStatus,
+        ) -> Result<(), Error> {
+            dispute.status = status;
+            if status == DisputeStatus::Upheld {
+                let commitment =
+                    self.get_overturnable_commitment(dispute.captcha_solution_commitment_id)?;
+                // the provider compensates the claimant for the incorrect verdict
+                self.provider_slash(commitment.provider, Some(dispute.claimant))?;
+                self.dispute_overturn_verdict(dispute.captcha_solution_commitment_id, commitment);
+                self.provider_update_stats(commitment.provider, |stats| stats.disputes_lost += 1);
+            }
+            self.disputes.insert(dispute_id, &dispute);
+            self.env().emit_event(DisputeResolve { dispute_id, status });
+            Ok(())
+        }
+
+        /// Get a solution commitment whose verdict can be overturned
+        fn get_overturnable_commitment(
+            &self,
+            captcha_solution_commitment_id: Hash,
+        ) -> Result<CaptchaSolutionCommitment, Error> {
+            let commitment =
+                self.get_captcha_solution_commitment(captcha_solution_commitment_id)?;
+            self.get_dapp_user(commitment.account)?;
+            match commitment.status {
+                CaptchaStatus::Pending => Err(Error::CaptchaSolutionCommitmentPending),
+                CaptchaStatus::Expired => Err(Error::CaptchaSolutionCommitmentExpired),
+                CaptchaStatus::Approved | CaptchaStatus::Disapproved => Ok(commitment),
+            }
+        }
+
+        /// Reverse the status of a commitment and correct the dapp user's captcha counts
+        ///
+        /// The commitment must have been checked with `get_overturnable_commitment`
+        fn dispute_overturn_verdict(
+            &mut self,
+            captcha_solution_commitment_id: Hash,
+            mut commitment: CaptchaSolutionCommitment,
+        ) {
+            match commitment.status {
+                CaptchaStatus::Approved => {
+                    commitment.status = CaptchaStatus::Disapproved;
+                    self.dapp_user_update_records(
+                        commitment.account,
+                        commitment.contract,
+                        |user| {
+                            user.correct_captchas = user.correct_captchas.saturating_sub(1);
+                            user.incorrect_captchas += 1;
+                        },
+                    );
+                }
+                CaptchaStatus::Disapproved => {
+                    commitment.status = CaptchaStatus::Approved;
+                    self.dapp_user_update_records(
+                        commitment.account,
+                        commitment.contract,
+                        |user| {
+                            user.incorrect_captchas = user.incorrect_captchas.saturating_sub(1);
+                            user.correct_captchas += 1;
+                        },
+                    );
+                }
+                CaptchaStatus::Pending | CaptchaStatus::Expired => return,
+            }
+            self.dapp_user_update_last_correct_captcha(
+                captcha_solution_commitment_id,
+                &mut commitment,
+            );
+            self.captcha_solution_commitments
+                .insert(captcha_solution_commitment_id, &commitment);
+        }
+
+        /// Count the distinct operators that are Active
+        fn get_active_operator_count(&self) -> u32 {
+            let active_operators: BTreeSet<AccountId> = self
+                .operator_accounts
+                .iter()
+                .filter(|account| self.validate_operator(**account).is_ok())
+                .copied()
+                .collect();
+            active_operators.len() as u32
+        }
+
+        /// Slash a provider, paying the slashed stake to the harmed party or into the treasury
+        #[ink(message)]
+        pub fn operator_slash_provider(
+            &mut self,
+            provider_account: AccountId,
+            beneficiary: Option<AccountId>,
+        ) -> Result<Balance, Error> {
+            let caller = self.env().caller();
+            self.validate_operator(caller)?;
+            self.provider_slash(provider_account, beneficiary)
+        }
+
+        /// Pay `amount` of the slashed stake held by the protocol treasury to `to`
+        #[ink(message)]
+        pub fn operator_withdraw_treasury(
+            &mut self,
+            to: AccountId,
+            amount: Balance,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.validate_operator(caller)?;
+            if amount == 0 {
+                return Err(Error::InvalidAmount);
+            }
+            let treasury_balance = self
+                .treasury_balance
+                .checked_sub(amount)
+                .ok_or(Error::TreasuryInsufficientFunds)?;
+            if self.env().transfer(to, amount).is_err() {
+                return Err(Error::ContractTransferFailed);
+            }
+            self.treasury_balance = treasury_balance;
+            self.env().emit_event(TreasuryWithdraw {
+                operator: caller,
+                to,
+                value: amount,
+            });
+            Ok(())
+        }
+
+        /// Set the protocol parameters
+        ///
+        /// Providers are not re-evaluated against a new minimum stake here, operators do so in
+        /// batches with `operator_reevaluate_providers`. Until then, providers below the minimum
+        /// stake are not selected and cannot give verdicts
+        #[ink(message)]
+        pub fn operator_set_protocol_config(
+            &mut self,
+            config: ProtocolConfig,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.validate_operator(caller)?;
+            if config.provider_stake_default == 0
+                || config.provider_stake_default > MAX_PROVIDER_STAKE
+                || config.operator_stake_default > MAX_OPERATOR_STAKE
+                || config.provider_fee_max > MAX_PROVIDER_FEE_MAX
+                || config.dapp_refund_max > MAX_DAPP_REFUND_MAX
+                || config.provider_unbonding_period > MAX_PROVIDER_UNBONDING_PERIOD
+                || config.dispute_window == 0
+                || config.dispute_window > MAX_DISPUTE_WINDOW
+                || config.dispute_voting_period == 0
+                || config.dispute_voting_period > MAX_DISPUTE_VOTING_PERIOD
+                || config.provider_slash_percent > 100
+                || config.commitment_expiry == 0
+                || config.commitment_expiry > MAX_COMMITMENT_EXPIRY
+                || config.reveal_window == 0
+                || config.reveal_window > MAX_REVEAL_WINDOW
+            {
+                return Err(Error::InvalidProtocolConfig);
+            }
+            self.provider_stake_default = config.provider_stake_default;
+            self.operator_stake_default = config.operator_stake_default;
+            self.provider_fee_max = config.provider_fee_max;
+            self.dapp_refund_max = config.dapp_refund_max;
+            self.provider_unbonding_period = config.provider_unbonding_period;
+            self.dispute_window = config.dispute_window;
+            self.dispute_voting_period = config.dispute_voting_period;
+            self.provider_slash_percent = config.provider_slash_percent;
+            self.provider_selection_by_reputation = config.provider_selection_by_reputation;
+            self.commitment_expiry = config.commitment_expiry;
+            self.reveal_window = config.reveal_window;
+
+            self.env().emit_event(ProtocolConfigUpdate {
+                operator: caller,
+                config,
+            });
+            Ok(())
+        }
+
+        // Informational / Validation functions
+
+        fn validate_operator(&self, operator_id: AccountId) -> Result<(), Error> {
+            match self.operators.get(&operator_id) {
+                Some(operator) if operator.status == GovernanceStatus::Active => Ok(()),
+                _ => {
+                    ink_env::debug_println!("{}", "NotAuthorised");
+                    Err(Error::NotAuthorised)
+                }
+            }
+        }
+
+        fn validate_provider(&self, provider_id: AccountId) -> Result<(), Error> {
+            if self.providers.get(&provider_id).is_none() {
+                ink_env::debug_println!("{}", "ProviderDoesNotExist");
+                return Err(Error::ProviderDoesNotExist);
+            }
+            let provider = self.get_provider_details(provider_id)?;
+            if provider.status != GovernanceStatus::Active {
+                ink_env::debug_println!("{}", "ProviderInactive");
+                return Err(Error::ProviderInactive);
+            }
+            // the minimum stake may have been raised since the provider was last re-evaluated
+            if !self.provider_meets_minimum_stake(provider.balance) {
+                ink_env::debug_println!("{}", "ProviderInsufficientFunds");
+                return Err(Error::ProviderInsufficientFunds);
+            }
+            Ok(())
+        }
+
+        fn validate_dapp(&self, contract: AccountId) -> Result<(), Error> {
+            // Guard against dapps using service that are not registered
+            if self.dapps.get(&contract).is_none() {
+                ink_env::debug_println!("{}", "DappDoesNotExist");
+                return Err(Error::DappDoesNotExist);
+            }
+            // Guard against dapps using service that are Suspended or Deactivated
+            let dapp = self.get_dapp_details(contract)?;
+            if dapp.status != GovernanceStatus::Active {
+                ink_env::debug_println!("{}", "DappInactive");
+                return Err(Error::DappInactive);
+            }
+            // Guard against registrations made without the dapp contract's consent
+            if !dapp.verified {
+                ink_env::debug_println!("{}", "DappNotVerified");
+                return Err(Error::DappNotVerified);
+            }
+            // Make sure the Dapp can pay the transaction fees of the user and potentially the
+            // provider, if their fee > 0
+            if dapp.balance <= 0 {
+                ink_env::debug_println!("{}", "DappInsufficientFunds");
+                return Err(Error::DappInsufficientFunds);
+            }
+            Ok(())
+        }
+
+        /// Get a single captcha dataset
+        ///
+        /// Returns an error if the dapp does not exist
+        #[ink(message)]
+        pub fn get_captcha_data(&self, captcha_dataset_id: Hash) -> Result<CaptchaData, Error> {
+            if self.captcha_data.get(&captcha_dataset_id).is_none() {
+                ink_env::debug_println!("{}", "CaptchaDatasetDoesNotExist");
+                return Err(Error::CaptchaDataDoesNotExist);
+            }
+            let captcha_data = self.captcha_data.get(&captcha_dataset_id);
+            Ok(captcha_data.unwrap())
+        }
+
+        /// List the data sets in a provider's catalogue, including retired data sets
+        ///
+        /// Returns empty if the provider has no data sets
+        #[ink(message)]
+        pub fn list_provider_datasets(&self, provider: AccountId) -> Vec<CaptchaData> {
+            self.provider_datasets
+                .get(provider)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|merkle_tree_root| self.captcha_data.get(merkle_tree_root))
+                .collect()
+        }
+
+        /// Get the AccountIds of the Active providers with an active data set that matches the
+        /// given requirements
+        ///
+        /// An empty `captcha_types` matches any captcha type
+        #[ink(message)]
+        pub fn get_provider_ids_by_dataset(
+            &self,
+            captcha_types: Vec<CaptchaType>,
+            min_difficulty: u16,
+            min_captcha_count: u32,
+        ) -> Vec<AccountId> {
+            self.provider_accounts
+                .get(GovernanceStatus::Active)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|provider_id| {
+                    self.list_provider_datasets(*provider_id)
+                        .iter()
+                        .any(|dataset| {
+                            Self::captcha_data_meets_requirements(
+                                dataset,
+                                &captcha_types,
+                                min_difficulty,
+                                min_captcha_count,
+                            )
+                        })
+                })
+                .collect()
+        }
+
+        /// Returns true if the data set is active and matches the given requirements
+        fn captcha_data_meets_requirements(
+            dataset: &CaptchaData,
+            captcha_types: &[CaptchaType],
+            min_difficulty: u16,
+            min_captcha_count: u32,
+        ) -> bool {
+            dataset.status == CaptchaDataStatus::Active
+                && (captcha_types.is_empty() || captcha_types.contains(&dataset.captcha_type))
+                && dataset.difficulty >= min_difficulty
+                && dataset.captcha_count >= min_captcha_count
+        }
+
+        /// Returns true if the data set is active and meets the dapp's captcha requirements
+        fn captcha_data_meets_dapp_requirements(dataset: &CaptchaData, dapp: &Dapp) -> bool {
+            Self::captcha_data_meets_requirements(
+                dataset,
+                &dapp.captcha_types,
+                dapp.min_difficulty,
+                dapp.min_captcha_count,
+            )
+        }
+
+        /// Returns true if the provider's current data set exists and is active
+        fn provider_has_active_dataset(&self, provider_id: AccountId) -> bool {
+            self.providers
+                .get(provider_id)
+                .and_then(|provider| self.captcha_data.get(provider.captcha_dataset_id))
+                .map_or(false, |dataset| dataset.status == CaptchaDataStatus::Active)
+        }
+
+        /// Get the most recently added data set of a provider that meets the dapp's captcha
+        /// requirements
+        fn get_provider_dataset_for_dapp(
+            &self,
+            provider_id: AccountId,
+            dapp: &Dapp,
+        ) -> Option<Hash> {
+            self.list_provider_datasets(provider_id)
+                .iter()
+                .rev()
+                .find(|dataset| Self::captcha_data_meets_dapp_requirements(dataset, dapp))
+                .map(|dataset| dataset.merkle_tree_root)
+        }
+
+        /// Get a solution commitment
+        ///
+        /// Returns an error if the commitment does not exist
+        #[ink(message)]
+        pub fn get_captcha_solution_commitment(
+            &self,
+            captcha_solution_commitment_id: Hash,
+        ) -> Result<CaptchaSolutionCommitment, Error> {
+            if self
+                .captcha_solution_commitments
+                .get(&captcha_solution_commitment_id)
+                .is_none()
+            {
+                return Err(Error::CaptchaSolutionCommitmentDoesNotExist);
+            }
+            let commitment = self
+                .captcha_solution_commitments
+                .get(&captcha_solution_commitment_id)
+                .unwrap();
+
+            Ok(commitment)
+        }
+
+        /// Get a dispute
+        ///
+        /// Returns an error if the dispute does not exist
+        #[ink(message)]
+        pub fn get_dispute(&self, dispute_id: u64) -> Result<Dispute, Error> {
+            self.disputes
+                .get(dispute_id)
+                .ok_or(Error::DisputeDoesNotExist)
+        }
+
+        /// Get the id of the dispute opened against a solution commitment
+        ///
+        /// Returns an error if the commitment has not been disputed
+        #[ink(message)]
+        pub fn get_commitment_dispute_id(
+            &self,
+            captcha_solution_commitment_id: Hash,
+        ) -> Result<u64, Error> {
+            self.commitment_disputes
+                .get(captcha_solution_commitment_id)
+                .ok_or(Error::DisputeDoesNotExist)
+        }
+
+        /// Get a dapp user
+        ///
+        /// Returns an error if the user does not exist
+        #[ink(message)]
+        pub fn get_dapp_user(&self, dapp_user_id: AccountId) -> Result<User, Error> {
+            if self.dapp_users.get(&dapp_user_id).is_none() {
+                ink_env::debug_println!("{}", "DappUserDoesNotExist");
+                return Err(Error::DappUserDoesNotExist);
+            }
+            Ok(self.dapp_users.get(&dapp_user_id).unwrap())
+        }
+
+        /// Get a dapp user's record scoped to a single dapp
+        ///
+        /// Returns an error if the user has no solutions with a verdict for the dapp
+        #[ink(message)]
+        pub fn get_dapp_user_for_dapp(
+            &self,
+            dapp_user_id: AccountId,
+            contract: AccountId,
+        ) -> Result<User, Error> {
+            self.dapp_user_dapp_records
+                .get((contract, dapp_user_id))
+                .ok_or(Error::DappUserDoesNotExist)
+        }
+
+        /// Get a single provider's details
+        ///
+        /// Returns an error if the user does not exist
+        #[ink(message)]
+        pub fn get_provider_details(&self, accountid: AccountId) -> Result<Provider, Error> {
+            if self.providers.get(&accountid).is_none() {
+                ink_env::debug_println!("{}", "ProviderDoesNotExist");
+                return Err(Error::ProviderDoesNotExist);
+            }
+            let provider = self.providers.get(&accountid);
+            Ok(provider.unwrap())
+        }
+
+        /// Get a single dapps details
+        ///
+        /// Returns an error if the dapp does not exist
+        #[ink(message)]
+        pub fn get_dapp_details(&self, contract: AccountId) -> Result<Dapp, Error> {
+            if self.dapps.get(&contract).is_none() {
+                ink_env::debug_println!("{}", "DappDoesNotExist");
+                return Err(Error::DappDoesNotExist);
+            }
+            let dapp = self.dapps.get(&contract);
+            Ok(dapp.unwrap())
+        }
+
+        /// Returns the providers on the allow-list of the specified `dapp`
+        #[ink(message)]
+        pub fn get_dapp_provider_allow_list(&self, dapp: AccountId) -> Vec<AccountId> {
+            self.dapp_provider_allow_lists
+                .get(dapp)
+                .unwrap_or_default()
+                .into_iter()
+                .collect()
+        }
+
+        /// Returns the providers on the deny-list of the specified `dapp`
+        #[ink(message)]
+        pub fn get_dapp_provider_deny_list(&self, dapp: AccountId) -> Vec<AccountId> {
+            self.dapp_provider_deny_lists
+                .get(dapp)
+                .unwrap_or_default()
+                .into_iter()
+                .collect()
+        }
+
+        /// Returns true if the provider is allowed, or the allow-list is empty, and not denied
+        fn dapp_permits_provider(&self, contract: AccountId, provider: AccountId) -> bool {
+            let allow_list = self
+                .dapp_provider_allow_lists
+                .get(contract)
+                .unwrap_or_default();
+            let deny_list = self
+                .dapp_provider_deny_lists
+                .get(contract)
+                .unwrap_or_default();
+            (allow_list.is_empty() || allow_list.contains(&provider))
+                && !deny_list.contains(&provider)
+        }
+
+        /// Returns the proposed owner of the specified `dapp`, if any
+        #[ink(message)]
+        pub fn get_dapp_pending_owner(&self, dapp: AccountId) -> Option<AccountId> {
+            self.dapp_pending_owners.get(dapp)
+        }
+
+        /// Returns the account balance for the specified `dapp`.
+        ///
+        /// Returns `0` if the account does not exist.
+        #[ink(message)]
+        pub fn get_dapp_balance(&self, dapp: AccountId) -> Balance {
+            return match self.get_dapp_details(dapp) {
+                Ok(v) => v.balance,
+                Err(_e) => Balance::from(0_u32),
+            };
+        }
+
+        /// Returns the account balance for the specified `provider`.
+        ///
+        /// Returns `0` if the account does not exist.
+        #[ink(message)]
+        pub fn get_provider_balance(&self, provider: AccountId) -> Balance {
+            return match self.get_provider_details(provider) {
+                Ok(v) => v.balance,
+                Err(_e) => Balance::from(0_u32),
+            };
+        }
+
+        /// Re-evaluate the status of the given providers against the current minimum stake
+        #[ink(message)]
+        pub fn operator_reevaluate_providers(
+            &mut self,
+            provider_accounts: Vec<AccountId>,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.validate_operator(caller)?;
+            if provider_accounts.len() > MAX_PROVIDER_REEVALUATION_BATCH {
+                return Err(Error::TooManyProviders);
+            }
+            for provider_account in provider_accounts {
+                self.provider_reevaluate_status(provider_account);
+            }
+            Ok(())
+        }
+
+        /// Get the protocol parameters
+        #[ink(message)]
+        pub fn get_protocol_config(&self) -> ProtocolConfig {
+            ProtocolConfig {
+                provider_stake_default: self.provider_stake_default,
+                operator_stake_default: self.operator_stake_default,
+                provider_fee_max: self.provider_fee_max,
+                dapp_refund_max: self.dapp_refund_max,
+                provider_unbonding_period: self.provider_unbonding_period,
+                dispute_window: self.dispute_window,
+                dispute_voting_period: self.dispute_voting_period,
+                provider_slash_percent: self.provider_slash_percent,
+                provider_selection_by_reputation: self.provider_selection_by_reputation,
+                commitment_expiry: self.commitment_expiry,
+                reveal_window: self.reveal_window,
+            }
+        }
+
+        /// Returns the balance of slashed stake held by the protocol treasury
+        #[ink(message)]
+        pub fn get_treasury_balance(&self) -> Balance {
+            self.treasury_balance
+        }
+
+        /// Get a single provider's performance record
+        ///
+        /// Returns an error if the provider does not exist
+        #[ink(message)]
+        pub fn get_provider_stats(&self, provider: AccountId) -> Result<ProviderStats, Error> {
+            self.get_provider_details(provider)?;
+            Ok(self.provider_stats.get(provider).unwrap_or_default())
+        }
+
+        /// Returns the unbonding stake of the specified `provider`.
+        ///
+        /// Returns empty if the provider has no unbonding stake
+        #[ink(message)]
+        pub fn get_provider_unbonding(&self, provider: AccountId) -> Vec<UnbondingChunk> {
+            self.provider_unbonding.get(provider).unwrap_or_default()
+        }
+
+        /// List providers given an array of account id
+        ///
+        /// Returns empty if none were matched
+        #[ink(message)]
+        pub fn list_providers_by_ids(&self, provider_ids: Vec<AccountId>) -> Vec<Provider> {
+            let mut providers = Vec::new();
+            for provider_id in provider_ids {
+                let provider = self.providers.get(provider_id);
+                if provider.is_none() {
+                    continue;
+                }
+                providers.push(provider.unwrap())
+            }
+            providers
+        }
+
+        /// List providers given an array of status
+        ///
+        /// Returns empty if none were matched
+        #[ink(message)]
+        pub fn list_providers_by_status(&self, statuses: Vec<GovernanceStatus>) -> Vec<Provider> {
+            let mut providers = Vec::<Provider>::new();
+            for status in statuses {
+                let providers_set = self.provider_accounts.get(status);
+                if providers_set.is_none() {
+                    continue;
+                }
+                let provider_ids = providers_set.unwrap().into_iter().collect();
+                providers.append(&mut self.list_providers_by_ids(provider_ids));
+            }
+            providers
+        }
+
+        /// List the account ids of dapps given an array of status
+        ///
+        /// Returns empty if none were matched
+        #[ink(message)]
+        pub fn list_dapps_by_status(&self, statuses: Vec<GovernanceStatus>) -> Vec<AccountId> {
+            self.dapp_accounts
+                .iter()
+                .filter(|contract| {
+                    self.dapps
+                        .get(*contract)
+                        .map_or(false, |dapp| statuses.contains(&dapp.status))
+                })
+                .copied()
+                .collect()
+        }
+
+        /// Get a random active provider
+        ///
+        /// The probability of a provider being selected is proportional to their stake and,
+        /// if enabled in the protocol config, their reputation. The selection is deterministic
+        /// for a given block and `user_account`. Providers without an active data set are
+        /// skipped.
+        ///
+        /// Returns error if no active providers is found
+        #[ink(message)]
+        pub fn get_random_active_provider(
+            &self,
+            user_account: AccountId,
+        ) -> Result<RandomProvider, Error> {
+            let active_providers = self
+                .provider_accounts
+                .get(GovernanceStatus::Active)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|provider_id| self.provider_has_active_dataset(*provider_id))
+                .collect();
+            let provider_id = self.select_random_provider(active_providers, user_account)?;
+            let provider = self.providers.get(provider_id).unwrap();
+            Ok(RandomProvider {
+                provider,
+                dataset_id: provider.captcha_dataset_id,
+                block_number: self.env().block_number(),
+            })
+        }
+
+        /// Get a random active provider that is permitted by the dapp's allow-list and deny-list
+        /// and has a data set that meets the dapp's captcha requirements
+        ///
+        /// Returns error if no active provider meets the requirements
+        #[ink(message)]
+        pub fn get_random_active_provider_for_dapp(
+            &self,
+            user_account: AccountId,
+            contract: AccountId,
+        ) -> Result<RandomProvider, Error> {
+            let dapp = self.get_dapp_details(contract)?;
+            let candidates: Vec<(AccountId, Hash)> = self
+                .provider_accounts
+                .get(GovernanceStatus::Active)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|provider_id| self.dapp_permits_provider(contract, *provider_id))
+                .filter_map(|provider_id| {
+                    self.get_provider_dataset_for_dapp(provider_id, &dapp)
+                        .map(|dataset_id| (provider_id, dataset_id))
+                })
+                .collect();
+            let provider_id = self.select_random_provider(
+                candidates
+                    .iter()
+                    .map(|(provider_id, _)| *provider_id)
+                    .collect(),
+                user_account,
+            )?;
+            let (_, dataset_id) = candidates
+                .into_iter()
+                .find(|(candidate_id, _)| *candidate_id == provider_id)
+                .ok_or(Error::NoActiveProviders)?;
+            Ok(RandomProvider {
+                provider: self.providers.get(provider_id).unwrap(),
+                dataset_id,
+                block_number: self.env().block_number(),
+            })
+        }
+
+        /// Select a provider from `provider_ids` at random, weighted by their selection weight
+        fn select_random_provider(
+            &self,
+            provider_ids: Vec<AccountId>,
+            user_account: AccountId,
+        ) -> Result<AccountId, Error> {
+            let weights: Vec<(AccountId, Balance)> = provider_ids
+                .into_iter()
+                .map(|provider_id| (provider_id, self.get_provider_selection_weight(provider_id)))
+                .filter(|(_, weight)| *weight > 0)
+                .collect();
+            let total_weight = weights.iter().fold(0, |total: Balance, (_, weight)| {
+                total.saturating_add(*weight)
+            });
+            if total_weight == 0 {
+                return Err(Error::NoActiveProviders);
+            }
+            let draw = self.get_random_number(0, total_weight - 1, user_account);
+            Self::select_weighted_provider(&weights, draw).ok_or(Error::NoActiveProviders)
+        }
+
+        /// Get the AccountIds of all Providers ever registered
+        ///
+        /// Returns {Vec<AccountId>}
+        #[ink(message)]
+        pub fn get_all_provider_ids(&self) -> Vec<AccountId> {
+            let mut provider_ids = Vec::<AccountId>::new();
+            for status in [
+                GovernanceStatus::Active,
+                GovernanceStatus::Suspended,
+                GovernanceStatus::Deactivated,
+            ] {
+                let providers_set = self.provider_accounts.get(status);
+                if providers_set.is_none() {
+                    continue;
+                }
+                provider_ids.append(&mut providers_set.unwrap().into_iter().collect());
+            }
+            provider_ids
+        }
+
+        /// The weight of a provider in random provider selection
+        ///
+        /// The weight is the provider's stake, scaled by the proportion of their verdicts that
+        /// were neither overturned in a dispute nor timed out when reputation weighting is enabled
+        fn get_provider_selection_weight(&self, provider_id: AccountId) -> Balance {
+            let stake = self.get_provider_balance(provider_id);
+            if !self.provider_meets_minimum_stake(stake) {
+                return 0;
+            }
+            if !self.provider_selection_by_reputation {
+                return stake;
+            }
+            let stats = self.provider_stats.get(provider_id).unwrap_or_default();
+            let total = Balance::from(stats.approved + stats.disapproved + stats.timed_out);
+            if total == 0 {
+                return stake;
+            }
+            let failed =
+                core::cmp::min(Balance::from(stats.disputes_lost + stats.timed_out), total);
+            let reputation_bps = (total - failed) * 10_000 / total;
+            stake / 10_000 * reputation_bps + stake % 10_000 * reputation_bps / 10_000
+        }
+
+        /// Select the provider whose cumulative weight range contains `draw`
+        ///
+        /// `draw` must be less than the sum of the weights
+        fn select_weighted_provider(
+            weights: &[(AccountId, Balance)],
+            draw: Balance,
+        ) -> Option<AccountId> {
+            let mut cumulative: Balance = 0;
+            for (provider_id, weight) in weights {
+                cumulative = cumulative.saturating_add(*weight);
+                if draw < cumulative {
+                    return Some(*provider_id);
+                }
+            }
+            None
+        }
+
+        fn get_random_number(&self, min: u128, max: u128, user_account: AccountId) -> u128 {
+            let random_seed = self.env().random(user_account.as_ref());
+            let mut seed_converted: [u8; 32] = Default::default();
+            seed_converted.copy_from_slice(random_seed.0.as_ref());
+            let mut rng = ChaChaRng::from_seed(seed_converted);
+            Self::random_in_range(&mut rng, min, max)
+        }
+
+        /// Sample a number uniformly from the inclusive range `min..=max`
+        ///
+        /// Draws below `2^128 % range` are rejected so that the remaining draws are an exact
+        /// multiple of the range and every number in it is equally likely
+        fn random_in_range(rng: &mut ChaChaRng, min: u128, max: u128) -> u128 {
+            if max <= min {
+                return min;
+            }
+            let range = max - min;
+            if range == u128::MAX {
+                return Self::random_u128(rng);
+            }
+            let range = range + 1;
+            let threshold = range.wrapping_neg() % range;
+            loop {
+                let draw = Self::random_u128(rng);
+                if draw >= threshold {
+                    return min + draw % range;
+                }
+            }
+        }
+
+        fn random_u128(rng: &mut ChaChaRng) -> u128 {
+            (u128::from(rng.next_u64()) << 64) | u128::from(rng.next_u64())
+        }
+    }
+
+    /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
+    /// module and test functions are marked with a `#[test]` attribute.
     /// The below code is technically just normal Rust code.
     #[cfg(not(feature = "ink-experimental-engine"))]
     #[cfg(test)]
@@ -1280,859 +3836,3637 @@ pub mod prosopo {
         use ink_env::hash::HashOutput;
         use ink_lang as ink;
 
-        /// Imports all the definitions from the outer scope so we can use them here.
-        use super::*;
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+
+        /// We test if the default constructor does its job.
+        #[ink::test]
+        fn test_default_works() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let contract = Prosopo::default(operator_account);
+            assert!(contract.operators.get(&operator_account).is_some());
+            assert!(contract.operator_accounts.contains(&operator_account));
+        }
+
+        /// Test provider register
+        #[ink::test]
+        fn test_provider_register() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let provider_account = AccountId::from([0x2; 32]);
+            let service_origin = str_to_hash("https://localhost:2424".to_string());
+            let fee: u32 = 0;
+            contract.provider_register(service_origin, fee, Payee::Provider, provider_account);
+            assert!(contract.providers.get(&provider_account).is_some());
+            assert!(contract
+                .provider_accounts
+                .get(GovernanceStatus::Deactivated)
+                .unwrap_or_default()
+                .contains(&provider_account));
+        }
+
+        /// Test provider deregister
+        #[ink::test]
+        fn test_provider_deregister() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let provider_account = AccountId::from([0x2; 32]);
+            let service_origin = str_to_hash("https://localhost:2424".to_string());
+            let fee: u32 = 0;
+            contract.provider_register(service_origin, fee, Payee::Provider, provider_account);
+            assert!(contract.providers.get(&provider_account).is_some());
+            contract.provider_deregister(provider_account);
+            let provider_record = contract.providers.get(&provider_account).unwrap();
+            assert!(provider_record.status == GovernanceStatus::Deactivated);
+        }
+
+        /// Test list providers
+        #[ink::test]
+        fn test_list_providers_by_ids() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let provider_account = AccountId::from([0x2; 32]);
+            let service_origin = str_to_hash("https://localhost:2424".to_string());
+            let fee: u32 = 0;
+            contract.provider_register(service_origin, fee, Payee::Provider, provider_account);
+            let registered_provider_account = contract.providers.get(&provider_account);
+            assert!(registered_provider_account.is_some());
+            let returned_list = contract.list_providers_by_ids(vec![provider_account]);
+            assert!(returned_list == vec![registered_provider_account.unwrap()]);
+        }
+
+        // Test get random number
+        #[ink::test]
+        fn test_get_random_number() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let contract = Prosopo::default(operator_account);
+            let mut number = contract.get_random_number(1, 128, operator_account);
+            ink_env::debug_println!("{}", number);
+            assert!((1 <= number) && (number <= 128));
+
+            number = contract.get_random_number(0, 1, operator_account);
+            ink_env::debug_println!("{}", number);
+            assert!(number == 0 || number == 1);
+        }
+
+        /// Test random numbers are spread evenly across the whole range
+        #[ink::test]
+        fn test_random_in_range_distribution() {
+            let mut rng = ChaChaRng::from_seed([0x7; 32]);
+            let samples = 100_000;
+            let mut counts = [0u32; 10];
+            for _ in 0..samples {
+                let number = Prosopo::random_in_range(&mut rng, 10, 19);
+                assert!((10..=19).contains(&number));
+                counts[(number - 10) as usize] += 1;
+            }
+            // each of the 10 numbers is expected 10,000 times, allow a 5% deviation
+            for count in counts {
+                assert!(
+                    (9_500..=10_500).contains(&count),
+                    "count {} out of bounds",
+                    count
+                );
+            }
+        }
+
+        /// Test random numbers at the edges of the range
+        #[ink::test]
+        fn test_random_in_range_bounds() {
+            let mut rng = ChaChaRng::from_seed([0x9; 32]);
+            assert_eq!(Prosopo::random_in_range(&mut rng, 5, 5), 5);
+            let mut seen = [false; 2];
+            for _ in 0..100 {
+                seen[Prosopo::random_in_range(&mut rng, 0, 1) as usize] = true;
+            }
+            assert_eq!(seen, [true, true]);
+            // the full range does not overflow
+            Prosopo::random_in_range(&mut rng, 0, u128::MAX);
+            // the same seed produces the same numbers
+            let mut rng_a = ChaChaRng::from_seed([0x3; 32]);
+            let mut rng_b = ChaChaRng::from_seed([0x3; 32]);
+            assert_eq!(
+                Prosopo::random_in_range(&mut rng_a, 0, 1_000_000),
+                Prosopo::random_in_range(&mut rng_b, 0, 1_000_000)
+            );
+        }
+
+        /// Test weighted provider selection is proportional to the weights
+        #[ink::test]
+        fn test_select_weighted_provider_distribution() {
+            let provider_a = AccountId::from([0x2; 32]);
+            let provider_b = AccountId::from([0x3; 32]);
+            let provider_c = AccountId::from([0x4; 32]);
+            let weights = vec![(provider_a, 100), (provider_b, 300), (provider_c, 600)];
+
+            // every possible draw selects a provider, each in proportion to its weight
+            let mut counts = [0u128; 3];
+            for draw in 0..1000 {
+                match Prosopo::select_weighted_provider(&weights, draw) {
+                    Some(provider) if provider == provider_a => counts[0] += 1,
+                    Some(provider) if provider == provider_b => counts[1] += 1,
+                    Some(provider) if provider == provider_c => counts[2] += 1,
+                    _ => panic!("draw {} did not select a provider", draw),
+                }
+            }
+            assert_eq!(counts, [100, 300, 600]);
+            assert_eq!(Prosopo::select_weighted_provider(&weights, 1000), None);
+
+            // seeded random draws select each provider in proportion to its weight
+            let mut rng = ChaChaRng::from_seed([0x7; 32]);
+            let mut counts = [0u128; 3];
+            for _ in 0..10_000 {
+                let draw = Prosopo::random_in_range(&mut rng, 0, 999);
+                match Prosopo::select_weighted_provider(&weights, draw) {
+                    Some(provider) if provider == provider_a => counts[0] += 1,
+                    Some(provider) if provider == provider_b => counts[1] += 1,
+                    Some(provider) if provider == provider_c => counts[2] += 1,
+                    _ => panic!("draw {} did not select a provider", draw),
+                }
+            }
+            for (count, expected) in counts.iter().zip([1_000u128, 3_000, 6_000]) {
+                assert!(
+                    count.abs_diff(expected) < 250,
+                    "selected {} times, expected about {}",
+                    count,
+                    expected
+                );
+            }
+        }
+
+        /// Helper function for converting string to Hash
+        fn str_to_hash(str: String) -> Hash {
+            let mut result = Hash::default();
+            let len_result = result.as_ref().len();
+            let mut hash_output = <<Blake2x256 as HashOutput>::Type as Default>::default();
+            <Blake2x256 as CryptoHash>::hash((&str).as_ref(), &mut hash_output);
+            let copy_len = core::cmp::min(hash_output.len(), len_result);
+            result.as_mut()[0..copy_len].copy_from_slice(&hash_output[0..copy_len]);
+            result
+        }
+    }
+
+    #[cfg(feature = "ink-experimental-engine")]
+    #[cfg(test)]
+    mod tests_experimental_engine {
+        use ink_env::hash::Blake2x256;
+        use ink_env::hash::CryptoHash;
+        use ink_env::hash::HashOutput;
+        use ink_lang as ink;
+
+        use crate::prosopo::Error::ProviderInactive;
+
+        use super::*;
+        use ink_env::test::EmittedEvent;
+
+        type Event = <Prosopo as ::ink_lang::reflect::ContractEventBase>::Type;
+
+        /// Provider Register Helper
+        fn generate_provider_data(id: u8, port: &str, fee: u32) -> (AccountId, Hash, u32) {
+            let provider_account = AccountId::from([id; 32]);
+            let service_origin = str_to_hash(format!("https://localhost:{}", port));
+
+            (provider_account, service_origin, fee)
+        }
+
+        /// Test add operator
+        #[ink::test]
+        fn test_add_operator() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            let operator_account_new = AccountId::from([0x2; 32]);
+            contract.add_prosopo_operator(operator_account_new);
+            assert!(contract.operator_accounts.contains(&operator_account_new));
+            assert!(contract.operators.get(&operator_account_new).is_some());
+        }
+
+        /// Test operator set protocol config
+        #[ink::test]
+        fn test_operator_set_protocol_config() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let mut config = contract.get_protocol_config();
+            config.provider_stake_default = 500;
+            config.provider_fee_max = 10;
+            config.dapp_refund_max = 1000;
+
+            // only operators can change the protocol config
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(AccountId::from([0x2; 32]));
+            assert_eq!(
+                Error::NotAuthorised,
+                contract.operator_set_protocol_config(config).unwrap_err()
+            );
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            contract.operator_set_protocol_config(config).unwrap();
+            assert_eq!(contract.get_protocol_config(), config);
+
+            // out of bounds parameters are rejected
+            let mut invalid_configs = [config; 5];
+            invalid_configs[0].provider_unbonding_period = MAX_PROVIDER_UNBONDING_PERIOD + 1;
+            invalid_configs[1].provider_stake_default = 0;
+            invalid_configs[2].operator_stake_default = MAX_OPERATOR_STAKE + 1;
+            invalid_configs[3].provider_fee_max = MAX_PROVIDER_FEE_MAX + 1;
+            invalid_configs[4].dapp_refund_max = MAX_DAPP_REFUND_MAX + 1;
+            for invalid_config in invalid_configs {
+                assert_eq!(
+                    Error::InvalidProtocolConfig,
+                    contract
+                        .operator_set_protocol_config(invalid_config)
+                        .unwrap_err()
+                );
+            }
+            assert_eq!(contract.get_protocol_config(), config);
+
+            // providers cannot charge more than the maximum fee
+            let (provider_account, service_origin, _) = generate_provider_data(0x3, "4242", 0);
+            assert_eq!(
+                Error::ProviderFeeTooHigh,
+                contract
+                    .provider_register(service_origin, 11, Payee::Provider, provider_account)
+                    .unwrap_err()
+            );
+        }
+
+        /// Test provider register and update
+        #[ink::test]
+        fn test_provider_register_and_update() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "2424", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            assert!(contract.providers.get(&provider_account).is_some());
+            assert!(contract
+                .provider_accounts
+                .get(GovernanceStatus::Deactivated)
+                .unwrap()
+                .contains(&provider_account));
+            let service_origin = str_to_hash("https://localhost:4242".to_string());
+            let fee: u32 = 100;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            let balance = 1000;
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            contract.provider_update(service_origin, fee, Payee::Dapp, provider_account);
+            assert!(contract
+                .provider_accounts
+                .get(GovernanceStatus::Active)
+                .unwrap()
+                .contains(&provider_account));
+            let provider = contract.providers.get(&provider_account).unwrap();
+            assert_eq!(provider.service_origin, service_origin);
+            assert_eq!(provider.fee, fee);
+            assert_eq!(provider.payee, Payee::Dapp);
+            assert_eq!(provider.balance, balance);
+            assert_eq!(provider.status, GovernanceStatus::Active);
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+
+            // first event is the register event, second event is the update
+            assert_eq!(2, emitted_events.len());
+
+            let event_provider_update = &emitted_events[1];
+
+            let decoded_event_update =
+                <Event as scale::Decode>::decode(&mut &event_provider_update.data[..])
+                    .expect("encountered invalid contract event data buffer");
+
+            if let Event::ProviderUpdate(ProviderUpdate { account }) = decoded_event_update {
+                assert_eq!(
+                    account, provider_account,
+                    "encountered invalid ProviderUpdate.account"
+                );
+            } else {
+                panic!("encountered unexpected event kind: expected a ProviderUpdate event");
+            }
+        }
+
+        /// Test provider register with service_origin error
+        #[ink::test]
+        fn test_provider_register_with_service_origin_error() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+
+            // try creating the second provider and make sure the error is correct and that it doesn't exist
+            let (provider_account, _, _) = generate_provider_data(0x3, "4242", 0);
+            match contract.provider_register(service_origin, fee, Payee::Provider, provider_account)
+            {
+                Result::Err(Error::ProviderServiceOriginUsed) => {
+                    assert!(true);
+                }
+                _ => {
+                    assert!(false);
+                }
+            }
+            assert!(contract.providers.get(&provider_account).is_none());
+            assert!(!contract
+                .provider_accounts
+                .get(GovernanceStatus::Deactivated)
+                .unwrap()
+                .contains(&provider_account));
+        }
+
+        /// Test provider update with service_origin error
+        #[ink::test]
+        fn test_provider_update_with_service_origin_error() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+
+            let (provider_account, service_origin, fee) = generate_provider_data(0x3, "2424", 0);
+
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+
+            let (_, service_origin, fee) = generate_provider_data(0x3, "4242", 100);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            let balance = 1000;
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+
+            // try updating the second provider and make sure the error is correct and that it didn't change
+            match contract.provider_update(service_origin, fee, Payee::Dapp, provider_account) {
+                Result::Err(Error::ProviderServiceOriginUsed) => {
+                    assert!(true);
+                }
+                _ => {
+                    assert!(false);
+                }
+            }
+
+            let provider = contract.providers.get(&provider_account).unwrap();
+            assert_ne!(provider.service_origin, service_origin);
+            assert_ne!(provider.fee, fee);
+            assert_ne!(provider.balance, balance);
+            assert_ne!(provider.status, GovernanceStatus::Active);
+        }
+
+        /// Test provider unstake
+        #[ink::test]
+        fn test_provider_unstake() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            let balance: u128 = 10;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .ok();
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(
+                provider_account,
+                balance,
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            contract.provider_update(service_origin, fee, Payee::Provider, provider_account);
+            contract.provider_unstake(balance).ok();
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+
+            // events are the register event (0), stake event(1), deregister(2) and the unstake event(3)
+
+            assert_eq!(4, emitted_events.len());
+
+            let event_unstake = &emitted_events[3];
+            let decoded_event_unstake =
+                <Event as scale::Decode>::decode(&mut &event_unstake.data[..])
+                    .expect("encountered invalid contract event data buffer");
+
+            if let Event::ProviderUnstake(ProviderUnstake { account, value }) =
+                decoded_event_unstake
+            {
+                assert_eq!(
+                    account, provider_account,
+                    "encountered invalid ProviderUnstake.account"
+                );
+                assert_eq!(value, balance, "encountered invalid ProviderUnstake.value");
+            } else {
+                panic!("encountered unexpected event kind: expected a ProviderUnstake event");
+            }
+        }
+
+        /// Test provider partial unstake
+        #[ink::test]
+        fn test_provider_partial_unstake() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            let balance: u128 = 100;
+            let unstaked: u128 = 40;
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            contract.provider_unstake(unstaked).unwrap();
+
+            // the provider remains active with the remaining stake
+            let provider = contract.providers.get(&provider_account).unwrap();
+            assert_eq!(provider.balance, balance - unstaked);
+            assert_eq!(provider.status, GovernanceStatus::Active);
+
+            // the unstaked amount is unbonding and cannot be withdrawn yet
+            let unbonding = contract.get_provider_unbonding(provider_account);
+            assert_eq!(unbonding.len(), 1);
+            assert_eq!(unbonding[0].value, unstaked);
+            assert_eq!(contract.provider_withdraw_unbonded().unwrap(), 0);
+            assert_eq!(contract.get_provider_unbonding(provider_account), unbonding);
+
+            // cannot unstake more than the remaining stake
+            assert_eq!(
+                Error::ProviderInsufficientFunds,
+                contract.provider_unstake(balance).unwrap_err()
+            );
+        }
+
+        /// Test provider is suspended when their stake falls below the minimum
+        #[ink::test]
+        fn test_provider_suspended_below_minimum_stake() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            contract.provider_stake_default = 50;
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            assert_eq!(
+                contract.providers.get(&provider_account).unwrap().status,
+                GovernanceStatus::Active
+            );
+
+            // unstaking below the minimum suspends the provider
+            contract.provider_unstake(60).unwrap();
+            let provider = contract.providers.get(&provider_account).unwrap();
+            assert_eq!(provider.status, GovernanceStatus::Suspended);
+            assert!(contract
+                .provider_accounts
+                .get(GovernanceStatus::Suspended)
+                .unwrap()
+                .contains(&provider_account));
+            assert!(!contract
+                .provider_accounts
+                .get(GovernanceStatus::Active)
+                .unwrap()
+                .contains(&provider_account));
+
+            // events are the register (0), update (1), status change (2) and unstake (3)
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_eq!(4, emitted_events.len());
+            let decoded_event_status =
+                <Event as scale::Decode>::decode(&mut &emitted_events[2].data[..])
+                    .expect("encountered invalid contract event data buffer");
+            if let Event::ProviderStatusChange(ProviderStatusChange { account, status }) =
+                decoded_event_status
+            {
+                assert_eq!(account, provider_account);
+                assert_eq!(status, GovernanceStatus::Suspended);
+            } else {
+                panic!("encountered unexpected event kind: expected a ProviderStatusChange event");
+            }
+
+            // topping the stake back up reactivates the provider
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(60);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            assert_eq!(
+                contract.providers.get(&provider_account).unwrap().status,
+                GovernanceStatus::Active
+            );
+
+            // followed by the status change (4) and update (5)
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_eq!(6, emitted_events.len());
+            let decoded_event_status =
+                <Event as scale::Decode>::decode(&mut &emitted_events[4].data[..])
+                    .expect("encountered invalid contract event data buffer");
+            if let Event::ProviderStatusChange(ProviderStatusChange { account, status }) =
+                decoded_event_status
+            {
+                assert_eq!(account, provider_account);
+                assert_eq!(status, GovernanceStatus::Active);
+            } else {
+                panic!("encountered unexpected event kind: expected a ProviderStatusChange event");
+            }
+        }
+
+        /// Test provider add data set
+        #[ink::test]
+        //TODO off-chain environment does not yet support `block_timestamp`
+        fn test_provider_add_dataset() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            let balance: u128 = 10;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .ok();
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(
+                provider_account,
+                balance,
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            contract.provider_update(service_origin, fee, Payee::Provider, provider_account);
+            let root = str_to_hash("merkle tree".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .ok();
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+
+            // events are the register, stake, add data set
+            assert_eq!(3, emitted_events.len());
+
+            let event_unstake = &emitted_events[2];
+            let decoded_event_unstake =
+                <Event as scale::Decode>::decode(&mut &event_unstake.data[..])
+                    .expect("encountered invalid contract event data buffer");
+
+            if let Event::ProviderAddDataset(ProviderAddDataset {
+                account,
+                merkle_tree_root,
+            }) = decoded_event_unstake
+            {
+                assert_eq!(
+                    account, provider_account,
+                    "encountered invalid ProviderAddDataset.account"
+                );
+                assert_eq!(
+                    merkle_tree_root, root,
+                    "encountered invalid ProviderAddDataset.merkle_tree_root"
+                );
+            } else {
+                panic!("encountered unexpected event kind: expected a ProviderAddDataset event");
+            }
+        }
+
+        /// Test provider dataset catalogue
+        #[ink::test]
+        fn test_provider_datasets() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
+
+            // Register and fund the dapp, and verify it from the dapp contract
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
+            let first_root = str_to_hash("merkle tree root".to_string());
+            let second_root = str_to_hash("second merkle tree root".to_string());
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            contract
+                .provider_add_dataset(second_root, CaptchaType::ImageSelection, 10, 1, second_root)
+                .unwrap();
+            let datasets = contract.list_provider_datasets(provider_account);
+            assert_eq!(datasets.len(), 2);
+            assert!(datasets
+                .iter()
+                .all(|dataset| dataset.status == CaptchaDataStatus::Active));
+
+            // retiring the latest dataset falls back to the previous active dataset
+            contract.provider_retire_dataset(second_root).unwrap();
+            let provider = contract.providers.get(&provider_account).unwrap();
+            assert_eq!(provider.captcha_dataset_id, first_root);
+
+            // users can commit against the remaining active dataset but not the retired one
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(AccountId::from([0x6; 32]));
+            let user_root = str_to_hash("another user merkle tree root".to_string());
+            assert_eq!(
+                Error::CaptchaDataRetired,
+                contract
+                    .dapp_user_commit(
+                        dapp_contract_account,
+                        second_root,
+                        user_root,
+                        provider_account
+                    )
+                    .unwrap_err()
+            );
+            contract
+                .dapp_user_commit(
+                    dapp_contract_account,
+                    first_root,
+                    user_root,
+                    provider_account,
+                )
+                .unwrap();
+
+            // a retired dataset is only reactivated by adding it again with the same metadata
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            assert_eq!(
+                Error::CaptchaDataMetadataMismatch,
+                contract
+                    .provider_add_dataset(
+                        second_root,
+                        CaptchaType::ImageSelection,
+                        10,
+                        2,
+                        second_root
+                    )
+                    .unwrap_err()
+            );
+            let dataset = contract.get_captcha_data(second_root).unwrap();
+            assert_eq!(dataset.status, CaptchaDataStatus::Retired);
+            assert_eq!(dataset.difficulty, 1);
+            contract
+                .provider_add_dataset(second_root, CaptchaType::ImageSelection, 10, 1, second_root)
+                .unwrap();
+            assert_eq!(
+                contract.get_captcha_data(second_root).unwrap().status,
+                CaptchaDataStatus::Active
+            );
+            assert_eq!(contract.list_provider_datasets(provider_account).len(), 2);
+
+            // another provider cannot add or retire the provider's datasets
+            let (other_provider, service_origin, fee) = generate_provider_data(0x7, "2424", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, other_provider)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(other_provider);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, other_provider)
+                .unwrap();
+            assert_eq!(
+                Error::DuplicateCaptchaDataId,
+                contract
+                    .provider_add_dataset(
+                        first_root,
+                        CaptchaType::ImageSelection,
+                        10,
+                        1,
+                        first_root
+                    )
+                    .unwrap_err()
+            );
+            assert_eq!(
+                Error::NotAuthorised,
+                contract.provider_retire_dataset(first_root).unwrap_err()
+            );
+        }
+
+        /// Test filtering providers by their data set metadata
+        #[ink::test]
+        fn test_get_provider_ids_by_dataset() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
+            let root = str_to_hash("proof of work".to_string());
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            contract
+                .provider_add_dataset(root, CaptchaType::ProofOfWork, 1000, 5, root)
+                .unwrap();
+            let dataset = contract.get_captcha_data(root).unwrap();
+            assert_eq!(dataset.captcha_type, CaptchaType::ProofOfWork);
+            assert_eq!(dataset.captcha_count, 1000);
+            assert_eq!(dataset.difficulty, 5);
+            assert_eq!(dataset.solution_hash, root);
+
+            assert_eq!(
+                contract.get_provider_ids_by_dataset(vec![], 0, 0),
+                vec![provider_account]
+            );
+            assert_eq!(
+                contract.get_provider_ids_by_dataset(vec![CaptchaType::ProofOfWork], 5, 1000),
+                vec![provider_account]
+            );
+            assert!(contract
+                .get_provider_ids_by_dataset(vec![CaptchaType::Text], 0, 0)
+                .is_empty());
+            assert!(contract
+                .get_provider_ids_by_dataset(vec![CaptchaType::ProofOfWork], 6, 0)
+                .is_empty());
+
+            // retired datasets are not matched
+            contract.provider_retire_dataset(root).unwrap();
+            assert!(contract
+                .get_provider_ids_by_dataset(vec![CaptchaType::ProofOfWork], 0, 0)
+                .is_empty());
+        }
+
+        /// Test provider cannot add data set if inactive
+        #[ink::test]
+        fn test_provider_cannot_add_dataset_if_inactive() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            let balance: u128 = 10;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .ok();
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(
+                provider_account,
+                balance,
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            let root = str_to_hash("merkle tree".to_string());
+            let result = contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap_err();
+            assert_eq!(ProviderInactive, result)
+        }
+
+        /// Test dapp register with zero balance transfer
+        #[ink::test]
+        fn test_dapp_register_zero_balance_transfer() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let caller = AccountId::from([0x2; 32]);
+            let dapp_contract = AccountId::from([0x3; 32]);
+            // Call from the dapp account
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(caller);
+            // Don't transfer anything with the call
+            let balance = 0;
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract, None);
+            assert!(contract.dapps.get(&dapp_contract).is_some());
+            let dapp = contract.dapps.get(&dapp_contract).unwrap();
+            assert_eq!(dapp.owner, caller);
+            assert_eq!(dapp.client_origin, client_origin);
+
+            // account is marked as suspended as zero tokens have been paid
+            assert_eq!(dapp.status, GovernanceStatus::Suspended);
+            assert_eq!(dapp.balance, balance);
+            assert!(contract.dapp_accounts.contains(&dapp_contract));
+        }
+
+        /// Test dapp register with positive balance transfer
+        #[ink::test]
+        fn test_dapp_register_positive_balance_transfer() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let caller = AccountId::from([0x2; 32]);
+            let dapp_contract = AccountId::from([0x3; 32]);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+
+            // Call from the dapp account
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(caller);
+
+            // Transfer tokens with the call
+            let balance = 100;
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+
+            // register the dapp
+            contract.dapp_register(client_origin, dapp_contract, None);
+            // check the dapp exists in the hashmap
+            assert!(contract.dapps.get(&dapp_contract).is_some());
+
+            // check the various attributes are correct
+            let dapp = contract.dapps.get(&dapp_contract).unwrap();
+            assert_eq!(dapp.owner, caller);
+            assert_eq!(dapp.client_origin, client_origin);
+
+            // account is marked as active as balance is now positive
+            assert_eq!(dapp.status, GovernanceStatus::Active);
+            assert_eq!(dapp.balance, balance);
+            assert!(contract.dapp_accounts.contains(&dapp_contract));
+        }
+
+        /// Test dapp register and then update
+        #[ink::test]
+        //TODO fix tests so that register and update are separate
+        fn test_dapp_register_and_update() {
+            // TODO fix this test so that contract is actually updated, currently a new one is being
+            //   created
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let caller = AccountId::from([0x2; 32]);
+            let dapp_contract_account = AccountId::from([0x3; 32]);
+            let client_origin_1 = str_to_hash("https://localhost:2424".to_string());
+
+            // Call from the dapp account
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(caller);
+
+            // Transfer tokens with the call
+            let balance_1 = 100;
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance_1);
+
+            // register the dapp
+            contract.dapp_register(client_origin_1, dapp_contract_account, None);
+
+            // check the dapp exists in the hashmap
+            assert!(contract.dapps.get(&dapp_contract_account).is_some());
+
+            // check the various attributes are correct
+            let dapp = contract.dapps.get(&dapp_contract_account).unwrap();
+            assert_eq!(dapp.owner, caller);
+            assert_eq!(dapp.client_origin, client_origin_1);
+
+            // account is marked as active as tokens have been paid
+            assert_eq!(dapp.status, GovernanceStatus::Active);
+            assert_eq!(dapp.balance, balance_1);
+
+            // Transfer tokens with the call
+            let balance_2 = 200;
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance_2);
+
+            // run the register function again for the same (caller, contract) pair, adding more
+            // tokens and changing the client origin
+            let client_origin_2 = str_to_hash("https://localhost:2424".to_string()); // Implements `scale::Encode`
+            let new_owner = AccountId::from([0x5; 32]);
+            contract.dapp_register(client_origin_2, dapp_contract_account, Some(new_owner));
+
+            // check the various attributes are correct and the new owner is only proposed
+            let dapp = contract.dapps.get(&dapp_contract_account).unwrap();
+            assert_eq!(dapp.owner, caller);
+            assert_eq!(
+                contract.get_dapp_pending_owner(dapp_contract_account),
+                Some(new_owner)
+            );
+            assert_eq!(dapp.client_origin, client_origin_2);
+
+            // account is marked as active as tokens have been paid
+            assert_eq!(dapp.status, GovernanceStatus::Active);
+            assert_eq!(dapp.balance, balance_1 + balance_2);
+            assert!(contract.dapp_accounts.contains(&dapp_contract_account));
+
+            // a new dapp registered for another owner is owned by the caller until they accept
+            let other_dapp_contract_account = AccountId::from([0x6; 32]);
+            contract.dapp_register(
+                client_origin_1,
+                other_dapp_contract_account,
+                Some(new_owner),
+            );
+            let dapp = contract.dapps.get(&other_dapp_contract_account).unwrap();
+            assert_eq!(dapp.owner, caller);
+            assert_eq!(dapp.balance, balance_2);
+            assert_eq!(
+                contract.get_dapp_pending_owner(other_dapp_contract_account),
+                Some(new_owner)
+            );
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let decoded_event =
+                <Event as scale::Decode>::decode(&mut &emitted_events.last().unwrap().data[..])
+                    .expect("encountered invalid contract event data buffer");
+            if let Event::DappOwnershipProposed(DappOwnershipProposed {
+                contract: proposed_contract,
+                owner,
+                pending_owner,
+            }) = decoded_event
+            {
+                assert_eq!(proposed_contract, other_dapp_contract_account);
+                assert_eq!(owner, caller);
+                assert_eq!(pending_owner, new_owner);
+            } else {
+                panic!("encountered unexpected event kind: expected a DappOwnershipProposed event");
+            }
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(new_owner);
+            contract
+                .dapp_accept_ownership(other_dapp_contract_account)
+                .unwrap();
+            assert_eq!(
+                contract
+                    .dapps
+                    .get(&other_dapp_contract_account)
+                    .unwrap()
+                    .owner,
+                new_owner
+            );
+        }
+
+        /// Test dapp captcha requirements are enforced on commits and provider selection
+        #[ink::test]
+        fn test_dapp_configure() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
+
+            // Register and fund the dapp, and verify it from the dapp contract
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
+
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            let dapp_owner = AccountId::from([0x3; 32]);
+
+            // only the owner can configure the dapp
+            assert_eq!(
+                Error::NotAuthorised,
+                contract
+                    .dapp_configure(dapp_contract_account, 5, 0, vec![])
+                    .unwrap_err()
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_owner);
+            contract
+                .dapp_configure(
+                    dapp_contract_account,
+                    5,
+                    0,
+                    vec![CaptchaType::ImageSelection],
+                )
+                .unwrap();
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert_eq!(dapp.min_difficulty, 5);
+            assert_eq!(dapp.captcha_types, vec![CaptchaType::ImageSelection]);
+
+            // the provider's only dataset is too easy for the dapp
+            let user_account = AccountId::from([0x6; 32]);
+            assert_eq!(
+                Error::NoActiveProviders,
+                contract
+                    .get_random_active_provider_for_dapp(user_account, dapp_contract_account)
+                    .unwrap_err()
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let user_root = str_to_hash("another user merkle tree root".to_string());
+            assert_eq!(
+                Error::CaptchaDataDoesNotMeetDappRequirements,
+                contract
+                    .dapp_user_commit(dapp_contract_account, root, user_root, provider_account)
+                    .unwrap_err()
+            );
+
+            // a hard enough dataset is selected for the dapp
+            let hard_root = str_to_hash("hard merkle tree root".to_string());
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            contract
+                .provider_add_dataset(hard_root, CaptchaType::ImageSelection, 10, 5, hard_root)
+                .unwrap();
+            let selected = contract
+                .get_random_active_provider_for_dapp(user_account, dapp_contract_account)
+                .unwrap();
+            assert_eq!(selected.dataset_id, hard_root);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            contract
+                .dapp_user_commit(
+                    dapp_contract_account,
+                    hard_root,
+                    user_root,
+                    provider_account,
+                )
+                .unwrap();
+        }
+
+        /// Test dapp provider allow-lists and deny-lists
+        #[ink::test]
+        fn test_dapp_provider_lists() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
+
+            // Register and fund the dapp, and verify it from the dapp contract
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
+
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            let dapp_owner = AccountId::from([0x3; 32]);
+            let other_provider = AccountId::from([0x7; 32]);
+            let user_account = AccountId::from([0x6; 32]);
+
+            // only the owner can set the lists
+            assert_eq!(
+                Error::NotAuthorised,
+                contract
+                    .dapp_set_provider_deny_list(dapp_contract_account, vec![provider_account])
+                    .unwrap_err()
+            );
+
+            // a provider missing from a non-empty allow-list is excluded
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_owner);
+            contract
+                .dapp_set_provider_allow_list(dapp_contract_account, vec![other_provider])
+                .unwrap();
+            assert_eq!(
+                contract.get_dapp_provider_allow_list(dapp_contract_account),
+                vec![other_provider]
+            );
+            assert_eq!(
+                Error::NoActiveProviders,
+                contract
+                    .get_random_active_provider_for_dapp(user_account, dapp_contract_account)
+                    .unwrap_err()
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let user_root = str_to_hash("another user merkle tree root".to_string());
+            assert_eq!(
+                Error::ProviderNotPermittedByDapp,
+                contract
+                    .dapp_user_commit(dapp_contract_account, root, user_root, provider_account)
+                    .unwrap_err()
+            );
+
+            // a denied provider is excluded even when the allow-list is empty
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_owner);
+            contract
+                .dapp_set_provider_allow_list(dapp_contract_account, vec![])
+                .unwrap();
+            contract
+                .dapp_set_provider_deny_list(dapp_contract_account, vec![provider_account])
+                .unwrap();
+            assert_eq!(
+                Error::NoActiveProviders,
+                contract
+                    .get_random_active_provider_for_dapp(user_account, dapp_contract_account)
+                    .unwrap_err()
+            );
+
+            // removing the provider from the deny-list permits it again
+            contract
+                .dapp_set_provider_deny_list(dapp_contract_account, vec![])
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            contract
+                .dapp_user_commit(dapp_contract_account, root, user_root, provider_account)
+                .unwrap();
+        }
+
+        /// Test two step dapp ownership transfer
+        #[ink::test]
+        fn test_dapp_ownership_transfer() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let owner = AccountId::from([0x2; 32]);
+            let dapp_contract_account = AccountId::from([0x3; 32]);
+            let new_owner = AccountId::from([0x4; 32]);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(owner);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+
+            // only the owner can propose a new owner
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(new_owner);
+            assert_eq!(
+                Error::NotAuthorised,
+                contract
+                    .dapp_propose_owner(dapp_contract_account, new_owner)
+                    .unwrap_err()
+            );
+            assert_eq!(
+                Error::DappNoPendingOwner,
+                contract
+                    .dapp_accept_ownership(dapp_contract_account)
+                    .unwrap_err()
+            );
+
+            // a cancelled proposal cannot be accepted
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(owner);
+            contract
+                .dapp_propose_owner(dapp_contract_account, new_owner)
+                .unwrap();
+            contract
+                .dapp_cancel_ownership_transfer(dapp_contract_account)
+                .unwrap();
+            assert_eq!(contract.get_dapp_pending_owner(dapp_contract_account), None);
+
+            // only the proposed owner can accept
+            contract
+                .dapp_propose_owner(dapp_contract_account, new_owner)
+                .unwrap();
+            assert_eq!(
+                Error::NotAuthorised,
+                contract
+                    .dapp_accept_ownership(dapp_contract_account)
+                    .unwrap_err()
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(new_owner);
+            contract
+                .dapp_accept_ownership(dapp_contract_account)
+                .unwrap();
+            let dapp = contract.dapps.get(&dapp_contract_account).unwrap();
+            assert_eq!(dapp.owner, new_owner);
+            assert_eq!(contract.get_dapp_pending_owner(dapp_contract_account), None);
+        }
+
+        /// Test dapp fund account
+        #[ink::test]
+        fn test_dapp_fund() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let caller = AccountId::from([0x2; 32]);
+            let dapp_contract = AccountId::from([0x3; 32]);
+            let client_origin_1 = str_to_hash("https://localhost:2424".to_string());
+
+            // Call from the dapp account
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(caller);
+
+            // Transfer tokens with the register call
+            let balance_1 = 100;
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance_1);
+
+            // register the dapp
+            contract.dapp_register(client_origin_1, dapp_contract, None);
+
+            // Transfer tokens with the fund call
+            let balance_2 = 200;
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance_2);
+            contract.dapp_fund(dapp_contract);
+
+            // check the total account balance is correct
+            let dapp = contract.dapps.get(&dapp_contract).unwrap();
+            assert_eq!(dapp.balance, balance_1 + balance_2);
+        }
+
+        /// Test dapp cancel
+        #[ink::test]
+        fn test_dapp_cancel() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let caller = AccountId::from([0x2; 32]);
+            let contract_account = AccountId::from([0x3; 32]);
+            let client_origin_1 = str_to_hash("https://localhost:2424".to_string());
+            let callers_initial_balance =
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(caller).unwrap();
+
+            // Call from the dapp account
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(caller);
+
+            // Transfer tokens with the register call
+            let balance = 100;
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+
+            // register the dapp
+            contract.dapp_register(client_origin_1, contract_account, None);
+
+            // Transfer tokens with the fund call
+            contract.dapp_cancel(contract_account).ok();
+
+            // check the funds are returned and the dapp's status is Deactivated
+            let dapp = contract.dapps.get(&contract_account).unwrap();
+            assert_eq!(dapp.status, GovernanceStatus::Deactivated);
+
+            // Make sure the funds are returned to the caller
+            assert_eq!(dapp.balance, 0);
+            let callers_balance =
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(caller).unwrap();
+            assert_eq!(callers_initial_balance + balance, callers_balance);
+        }
+
+        /// Test dapp user commit
+        /// A dapp user can only commit a solution to the chain when there is at least one captcha
+        /// provider and one dapp available.
+        #[ink::test]
+        fn test_dapp_user_commit() {
+            let operator_account = AccountId::from([0x1; 32]);
+
+            // initialise the contract
+            let mut contract = Prosopo::default(operator_account);
+
+            // Register the provider
+            let provider_account = AccountId::from([0x2; 32]);
+            let service_origin = str_to_hash("https://localhost:2424".to_string());
+            let fee: u32 = 0;
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .ok();
+
+            // Call from the provider account to add data and stake tokens
+            let balance = 100;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            let root = str_to_hash("blah".to_string());
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            contract.provider_update(service_origin, fee, Payee::Provider, provider_account);
+            // can only add data set after staking
+            // TODO test scenario where dataset is added before staking
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .ok();
+
+            // Register the dapp
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+
+            // Call from the dapp account
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            // Give the dap a balance
+            let balance = 100;
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            let client_origin = service_origin.clone();
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+
+            // Call from the dapp contract to verify the registration
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+
+            //Dapp User commit
+            let user_root = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, user_root, provider_account)
+                .ok();
+
+            // check that the data is in the captcha_solution_commitments hashmap
+            assert!(contract
+                .captcha_solution_commitments
+                .get(&user_root)
+                .is_some());
+        }
+
+        /// Test provider approve
+        #[ink::test]
+        // TODO move the common stuff to a setup function
+        fn test_provider_approve() {
+            let operator_account = AccountId::from([0x1; 32]);
+
+            // initialise the contract
+            let mut contract = Prosopo::default(operator_account);
+
+            // Register the provider
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+
+            // Call from the provider account to add data and stake tokens
+            let balance = 100;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            let root = str_to_hash("merkle tree root".to_string());
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            contract.provider_update(service_origin, fee, Payee::Provider, provider_account);
+
+            let provider = contract.providers.get(&provider_account).unwrap();
+            // can only add data set after staking
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .ok();
+
+            // Register the dapp
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+
+            // Call from the dapp account
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            // Give the dap a balance
+            let balance = 100;
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            let client_origin = service_origin.clone();
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+
+            // Call from the dapp contract to verify the registration
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+
+            //Dapp User commit
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            let user_root = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, user_root, provider_account)
+                .ok();
+
+            // Call from the provider account to mark the solution as approved
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            let solution_id = user_root;
+            contract.provider_approve(solution_id);
+            let commitment = contract
+                .captcha_solution_commitments
+                .get(&solution_id)
+                .unwrap();
+            assert_eq!(commitment.status, CaptchaStatus::Approved);
+            let new_dapp_balance = contract.get_dapp_balance(dapp_contract_account);
+            let new_provider_balance = contract.get_provider_balance(provider_account);
+            assert_eq!(balance - Balance::from(fee), new_dapp_balance);
+            assert_eq!(balance + Balance::from(fee), new_provider_balance);
+
+            // Now make sure that the provider cannot later set the solution to disapproved and make
+            // sure that the dapp balance is unchanged
+            contract.provider_disapprove(solution_id);
+            let commitment = contract
+                .captcha_solution_commitments
+                .get(&solution_id)
+                .unwrap();
+            assert_eq!(commitment.status, CaptchaStatus::Approved);
+            assert_eq!(
+                balance - Balance::from(fee),
+                contract.get_dapp_balance(dapp_contract_account)
+            );
+            assert_eq!(
+                balance + Balance::from(fee),
+                contract.get_provider_balance(provider_account)
+            );
+        }
+
+        /// Test provider stats are updated with each verdict
+        #[ink::test]
+        fn test_get_provider_stats() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
+
+            // Register and fund the dapp, and verify it from the dapp contract
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
+
+            // Commit a solution as the dapp user
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let solution_id = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                .unwrap();
+            let stats = contract.get_provider_stats(provider_account).unwrap();
+            assert_eq!(stats.assigned, 1);
+            assert_eq!(stats.approved, 0);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            contract.provider_approve(solution_id, 0).unwrap();
+            let stats = contract.get_provider_stats(provider_account).unwrap();
+            assert_eq!(stats.assigned, 1);
+            assert_eq!(stats.approved, 1);
+            assert_eq!(stats.disapproved, 0);
+
+            assert_eq!(
+                Error::ProviderDoesNotExist,
+                contract
+                    .get_provider_stats(AccountId::from([0x9; 32]))
+                    .unwrap_err()
+            );
+        }
+
+        /// Test provider cannot approve invalid solution id
+        #[ink::test]
+        fn test_provider_approve_invalid_id() {
+            let operator_account = AccountId::from([0x1; 32]);
+
+            // initialise the contract
+            let mut contract = Prosopo::default(operator_account);
+
+            // Register the provider
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+
+            // Call from the provider account to add data and stake tokens
+            let balance = 100;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            let root = str_to_hash("merkle tree root".to_string());
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            contract.provider_update(service_origin, fee, Payee::Provider, provider_account);
+            // can only add data set after staking
+            // TODO test scenario where dataset is added before staking
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .ok();
+
+            // Register the dapp
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+
+            // Call from the dapp account
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            // Give the dap a balance
+            let balance = 100;
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            let client_origin = service_origin.clone();
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+
+            // Call from the dapp contract to verify the registration
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+
+            //Dapp User commit
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            let user_root = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, user_root, provider_account)
+                .ok();
+
+            // Call from the provider account to mark the wrong solution as approved
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            let solution_id = str_to_hash("id that does not exist".to_string());
+            let result = contract.provider_approve(solution_id);
+            assert_eq!(
+                Error::CaptchaSolutionCommitmentDoesNotExist,
+                result.unwrap_err()
+            );
+        }
+
+        /// Test provider disapprove
+        #[ink::test]
+        // TODO move the common stuff to a setup function
+        fn test_provider_disapprove() {
+            let operator_account = AccountId::from([0x1; 32]);
+
+            // initialise the contract
+            let mut contract = Prosopo::default(operator_account);
+
+            // Register the provider
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+
+            // Call from the provider account to add data and stake tokens
+            let balance = 100;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            let root = str_to_hash("merkle tree root".to_string());
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            contract.provider_update(service_origin, fee, Payee::Provider, provider_account);
+            // can only add data set after staking
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .ok();
+
+            // Register the dapp
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+
+            // Call from the dapp account
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            // Give the dap a balance
+            let balance = 100;
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+
+            // Call from the dapp contract to verify the registration
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+
+            //Dapp User commit
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            let user_root = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, user_root, provider_account)
+                .ok();
+
+            // Call from the provider account to mark the solution as disapproved
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            let solution_id = user_root;
+            contract.provider_disapprove(solution_id);
+            let commitment = contract
+                .captcha_solution_commitments
+                .get(&solution_id)
+                .unwrap();
+            assert_eq!(commitment.status, CaptchaStatus::Disapproved);
+            let new_dapp_balance = contract.get_dapp_balance(dapp_contract_account);
+            let new_provider_balance = contract.get_provider_balance(provider_account);
+            assert_eq!(balance - Balance::from(fee), new_dapp_balance);
+            assert_eq!(balance + Balance::from(fee), new_provider_balance);
+
+            // Now make sure that the provider cannot later set the solution to approved
+            contract.provider_approve(solution_id);
+            let commitment = contract
+                .captcha_solution_commitments
+                .get(&solution_id)
+                .unwrap();
+            assert_eq!(commitment.status, CaptchaStatus::Disapproved);
+            assert_eq!(
+                balance - Balance::from(fee),
+                contract.get_dapp_balance(dapp_contract_account)
+            );
+            assert_eq!(
+                balance + Balance::from(fee),
+                contract.get_provider_balance(provider_account)
+            );
+        }
+
+        /// Test a dapp user disputes a disapproval and the operators uphold it
+        #[ink::test]
+        fn test_dispute_upheld() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
+
+            // Register and fund the dapp, and verify it from the dapp contract
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
+
+            // Commit a solution as the dapp user
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let solution_id = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                .unwrap();
+
+            // a pending commitment cannot be disputed
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            assert_eq!(
+                Error::CaptchaSolutionCommitmentPending,
+                contract.dispute_open(solution_id).unwrap_err()
+            );
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            contract.provider_disapprove(solution_id).unwrap();
+
+            // only the dapp user or dapp owner can open a dispute
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            assert_eq!(
+                Error::NotAuthorised,
+                contract.dispute_open(solution_id).unwrap_err()
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let dispute_id = contract.dispute_open(solution_id).unwrap();
+            assert_eq!(
+                Error::DisputeExists,
+                contract.dispute_open(solution_id).unwrap_err()
+            );
+            assert_eq!(
+                contract.get_commitment_dispute_id(solution_id).unwrap(),
+                dispute_id
+            );
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let decoded_event = <Event as scale::Decode>::decode(
+                &mut &emitted_events[emitted_events.len() - 1].data[..],
+            )
+            .expect("encountered invalid contract event data buffer");
+            if let Event::DisputeOpen(DisputeOpen {
+                dispute_id: event_dispute_id,
+                captcha_solution_commitment_id,
+                claimant,
+            }) = decoded_event
+            {
+                assert_eq!(event_dispute_id, dispute_id);
+                assert_eq!(captcha_solution_commitment_id, solution_id);
+                assert_eq!(claimant, dapp_user_account);
+            } else {
+                panic!("encountered unexpected event kind: expected a DisputeOpen event");
+            }
+
+            // only operators can vote
+            assert_eq!(
+                Error::NotAuthorised,
+                contract.dispute_vote(dispute_id, true).unwrap_err()
+            );
+            let claimant_initial_balance = ink_env::test::get_account_balance::<
+                ink_env::DefaultEnvironment,
+            >(dapp_user_account)
+            .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            contract.dispute_vote(dispute_id, true).unwrap();
+
+            // the single operator is a majority so the dispute is resolved
+            let dispute = contract.get_dispute(dispute_id).unwrap();
+            assert_eq!(dispute.status, DisputeStatus::Upheld);
+            assert_eq!(dispute.votes_uphold, 1);
+            assert_eq!(
+                Error::DisputeClosed,
+                contract.dispute_vote(dispute_id, false).unwrap_err()
+            );
+
+            // the verdict and the user's counts are corrected
+            let commitment = contract
+                .get_captcha_solution_commitment(solution_id)
+                .unwrap();
+            assert_eq!(commitment.status, CaptchaStatus::Approved);
+            let user = contract.get_dapp_user(dapp_user_account).unwrap();
+            assert_eq!(user.correct_captchas, 1);
+            assert_eq!(user.incorrect_captchas, 0);
+
+            // the user's record for the dapp is corrected too
+            let dapp_record = contract
+                .get_dapp_user_for_dapp(dapp_user_account, dapp_contract_account)
+                .unwrap();
+            assert_eq!(dapp_record, user);
 
-        /// We test if the default constructor does its job.
-        #[ink::test]
-        fn test_default_works() {
-            let operator_account = AccountId::from([0x1; 32]);
-            let contract = Prosopo::default(operator_account);
-            assert!(contract.operators.get(&operator_account).is_some());
-            assert!(contract.operator_accounts.contains(&operator_account));
+            // the provider is slashed for the incorrect verdict and the claimant is paid
+            assert_eq!(contract.get_provider_balance(provider_account), 90);
+            assert_eq!(
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(
+                    dapp_user_account
+                )
+                .unwrap(),
+                claimant_initial_balance + 10
+            );
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let decoded_event = <Event as scale::Decode>::decode(
+                &mut &emitted_events[emitted_events.len() - 2].data[..],
+            )
+            .expect("encountered invalid contract event data buffer");
+            if let Event::DisputeResolve(DisputeResolve {
+                dispute_id: event_dispute_id,
+                status,
+            }) = decoded_event
+            {
+                assert_eq!(event_dispute_id, dispute_id);
+                assert_eq!(status, DisputeStatus::Upheld);
+            } else {
+                panic!("encountered unexpected event kind: expected a DisputeResolve event");
+            }
+            let decoded_event = <Event as scale::Decode>::decode(
+                &mut &emitted_events[emitted_events.len() - 1].data[..],
+            )
+            .expect("encountered invalid contract event data buffer");
+            if let Event::DisputeVote(DisputeVote {
+                dispute_id: event_dispute_id,
+                operator,
+                uphold,
+            }) = decoded_event
+            {
+                assert_eq!(event_dispute_id, dispute_id);
+                assert_eq!(operator, operator_account);
+                assert!(uphold);
+            } else {
+                panic!("encountered unexpected event kind: expected a DisputeVote event");
+            }
+            let stats = contract.get_provider_stats(provider_account).unwrap();
+            assert_eq!(stats.disputed, 1);
+            assert_eq!(stats.disputes_lost, 1);
         }
 
-        /// Test provider register
+        /// Test an upheld dispute against an approval falls back to the user's previous approval
         #[ink::test]
-        fn test_provider_register() {
+        fn test_dispute_overturns_approval() {
             let operator_account = AccountId::from([0x1; 32]);
             let mut contract = Prosopo::default(operator_account);
-            let provider_account = AccountId::from([0x2; 32]);
-            let service_origin = str_to_hash("https://localhost:2424".to_string());
-            let fee: u32 = 0;
-            contract.provider_register(service_origin, fee, Payee::Provider, provider_account);
-            assert!(contract.providers.get(&provider_account).is_some());
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
+
+            // Register and fund the dapp, and verify it from the dapp contract
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
+
+            // Commit a solution as the dapp user
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let solution_id = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                .unwrap();
+            let dapp_owner = AccountId::from([0x3; 32]);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            contract.provider_approve(solution_id, 0).unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let other_solution_id = str_to_hash("other user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(
+                    dapp_contract_account,
+                    root,
+                    other_solution_id,
+                    provider_account,
+                )
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            contract.provider_approve(other_solution_id, 0).unwrap();
+
+            // the dapp requires a recent correct captcha
+            let policy = HumanPolicy {
+                min_captchas: 1,
+                min_correct_bps: 0,
+                max_last_correct_age: 100,
+            };
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_owner);
+            contract
+                .dapp_set_human_policy(dapp_contract_account, policy)
+                .unwrap();
             assert!(contract
-                .provider_accounts
-                .get(GovernanceStatus::Deactivated)
-                .unwrap_or_default()
-                .contains(&provider_account));
-        }
+                .dapp_is_human(dapp_contract_account, dapp_user_account)
+                .unwrap());
+
+            // the dapp owner disputes the latest approval and the operator upholds it
+            let claimant_initial_balance =
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(dapp_owner)
+                    .unwrap();
+            let dispute_id = contract.dispute_open(other_solution_id).unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            contract.dispute_vote(dispute_id, true).unwrap();
+            let commitment = contract
+                .get_captcha_solution_commitment(other_solution_id)
+                .unwrap();
+            assert_eq!(commitment.status, CaptchaStatus::Disapproved);
+            assert_eq!(contract.get_provider_balance(provider_account), 90);
+            assert_eq!(
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(dapp_owner)
+                    .unwrap(),
+                claimant_initial_balance + 10
+            );
 
-        /// Test provider deregister
-        #[ink::test]
-        fn test_provider_deregister() {
-            let operator_account = AccountId::from([0x1; 32]);
-            let mut contract = Prosopo::default(operator_account);
-            let provider_account = AccountId::from([0x2; 32]);
-            let service_origin = str_to_hash("https://localhost:2424".to_string());
-            let fee: u32 = 0;
-            contract.provider_register(service_origin, fee, Payee::Provider, provider_account);
-            assert!(contract.providers.get(&provider_account).is_some());
-            contract.provider_deregister(provider_account);
-            let provider_record = contract.providers.get(&provider_account).unwrap();
-            assert!(provider_record.status == GovernanceStatus::Deactivated);
-        }
+            // the earlier approval is the user's last correct captcha again
+            let dapp_record = contract
+                .get_dapp_user_for_dapp(dapp_user_account, dapp_contract_account)
+                .unwrap();
+            assert_eq!(dapp_record.correct_captchas, 1);
+            assert_eq!(dapp_record.incorrect_captchas, 1);
+            assert_eq!(dapp_record.last_correct_captcha_id, solution_id);
+            assert_eq!(
+                contract.get_dapp_user(dapp_user_account).unwrap(),
+                dapp_record
+            );
+            assert!(contract
+                .dapp_is_human(dapp_contract_account, dapp_user_account)
+                .unwrap());
 
-        /// Test list providers
-        #[ink::test]
-        fn test_list_providers_by_ids() {
-            let operator_account = AccountId::from([0x1; 32]);
-            let mut contract = Prosopo::default(operator_account);
-            let provider_account = AccountId::from([0x2; 32]);
-            let service_origin = str_to_hash("https://localhost:2424".to_string());
-            let fee: u32 = 0;
-            contract.provider_register(service_origin, fee, Payee::Provider, provider_account);
-            let registered_provider_account = contract.providers.get(&provider_account);
-            assert!(registered_provider_account.is_some());
-            let returned_list = contract.list_providers_by_ids(vec![provider_account]);
-            assert!(returned_list == vec![registered_provider_account.unwrap()]);
+            // once that is overturned too the user has no correct captcha on record
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_owner);
+            let dispute_id = contract.dispute_open(solution_id).unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            contract.dispute_vote(dispute_id, true).unwrap();
+            let dapp_record = contract
+                .get_dapp_user_for_dapp(dapp_user_account, dapp_contract_account)
+                .unwrap();
+            assert_eq!(dapp_record.correct_captchas, 0);
+            assert_eq!(dapp_record.last_correct_captcha_id, Hash::default());
+            assert_eq!(contract.get_provider_balance(provider_account), 80);
+            assert_eq!(
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(dapp_owner)
+                    .unwrap(),
+                claimant_initial_balance + 20
+            );
+            assert!(!contract
+                .dapp_is_human(dapp_contract_account, dapp_user_account)
+                .unwrap());
         }
 
-        // Test get random number
+        /// Test a tied dispute is closed once its voting period ends
         #[ink::test]
-        fn test_get_random_number() {
+        fn test_dispute_close_after_tie() {
             let operator_account = AccountId::from([0x1; 32]);
-            let contract = Prosopo::default(operator_account);
-            let mut number = contract.get_random_number(1, 128, operator_account);
-            ink_env::debug_println!("{}", number);
-            assert!((1 <= number) && (number <= 128));
+            let mut contract = Prosopo::default(operator_account);
+            let second_operator = AccountId::from([0x9; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            contract.add_prosopo_operator(second_operator);
+            let mut config = contract.get_protocol_config();
+            config.dispute_voting_period = 2;
+            contract.operator_set_protocol_config(config).unwrap();
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
 
-            number = contract.get_random_number(0, 1, operator_account);
-            ink_env::debug_println!("{}", number);
-            assert!(number == 0 || number == 1);
-        }
+            // Register and fund the dapp, and verify it from the dapp contract
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
 
-        /// Helper function for converting string to Hash
-        fn str_to_hash(str: String) -> Hash {
-            let mut result = Hash::default();
-            let len_result = result.as_ref().len();
-            let mut hash_output = <<Blake2x256 as HashOutput>::Type as Default>::default();
-            <Blake2x256 as CryptoHash>::hash((&str).as_ref(), &mut hash_output);
-            let copy_len = core::cmp::min(hash_output.len(), len_result);
-            result.as_mut()[0..copy_len].copy_from_slice(&hash_output[0..copy_len]);
-            result
-        }
-    }
+            // Commit a solution as the dapp user
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let solution_id = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                .unwrap();
 
-    #[cfg(feature = "ink-experimental-engine")]
-    #[cfg(test)]
-    mod tests_experimental_engine {
-        use ink_env::hash::Blake2x256;
-        use ink_env::hash::CryptoHash;
-        use ink_env::hash::HashOutput;
-        use ink_lang as ink;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            contract.provider_disapprove(solution_id).unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let dispute_id = contract.dispute_open(solution_id).unwrap();
+            let claimant_initial_balance = ink_env::test::get_account_balance::<
+                ink_env::DefaultEnvironment,
+            >(dapp_user_account)
+            .unwrap();
 
-        use crate::prosopo::Error::ProviderInactive;
+            // one vote each way out of two operators is not a majority
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            contract.dispute_vote(dispute_id, true).unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(second_operator);
+            contract.dispute_vote(dispute_id, false).unwrap();
+            assert_eq!(
+                contract.get_dispute(dispute_id).unwrap().status,
+                DisputeStatus::Open
+            );
+            assert_eq!(
+                Error::DisputeVotingPeriodOpen,
+                contract.dispute_close(dispute_id).unwrap_err()
+            );
 
-        use super::*;
-        use ink_env::test::EmittedEvent;
+            // once the voting period ends the tie leaves the provider's verdict standing
+            advance_blocks(3);
+            assert_eq!(
+                Error::DisputeVotingPeriodEnded,
+                contract.dispute_vote(dispute_id, true).unwrap_err()
+            );
+            contract.dispute_close(dispute_id).unwrap();
+            assert_eq!(
+                contract.get_dispute(dispute_id).unwrap().status,
+                DisputeStatus::Rejected
+            );
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let decoded_event = <Event as scale::Decode>::decode(
+                &mut &emitted_events[emitted_events.len() - 1].data[..],
+            )
+            .expect("encountered invalid contract event data buffer");
+            if let Event::DisputeResolve(DisputeResolve {
+                dispute_id: event_dispute_id,
+                status,
+            }) = decoded_event
+            {
+                assert_eq!(event_dispute_id, dispute_id);
+                assert_eq!(status, DisputeStatus::Rejected);
+            } else {
+                panic!("encountered unexpected event kind: expected a DisputeResolve event");
+            }
+            assert_eq!(
+                contract
+                    .get_captcha_solution_commitment(solution_id)
+                    .unwrap()
+                    .status,
+                CaptchaStatus::Disapproved
+            );
+            assert_eq!(contract.get_provider_balance(provider_account), 100);
+            assert_eq!(
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(
+                    dapp_user_account
+                )
+                .unwrap(),
+                claimant_initial_balance
+            );
+            assert_eq!(
+                Error::DisputeClosed,
+                contract.dispute_close(dispute_id).unwrap_err()
+            );
+        }
 
-        type Event = <Prosopo as ::ink_lang::reflect::ContractEventBase>::Type;
+        /// Test a dispute whose slash cannot be paid is left open and unchanged
+        #[ink::test]
+        fn test_dispute_upheld_slash_transfer_failed() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
+            let solution_id = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            contract.provider_disapprove(solution_id).unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let dispute_id = contract.dispute_open(solution_id).unwrap();
+
+            // the contract cannot pay the slashed stake to the claimant
+            let contract_account = ink_env::test::callee::<ink_env::DefaultEnvironment>();
+            let contract_balance =
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(contract_account)
+                    .unwrap();
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(contract_account, 0);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            assert_eq!(
+                Error::ContractTransferFailed,
+                contract.dispute_vote(dispute_id, true).unwrap_err()
+            );
 
-        /// Provider Register Helper
-        fn generate_provider_data(id: u8, port: &str, fee: u32) -> (AccountId, Hash, u32) {
-            let provider_account = AccountId::from([id; 32]);
-            let service_origin = str_to_hash(format!("https://localhost:{}", port));
+            // nothing is written, the vote included
+            let dispute = contract.get_dispute(dispute_id).unwrap();
+            assert_eq!(dispute.status, DisputeStatus::Open);
+            assert_eq!(dispute.votes_uphold, 0);
+            assert_eq!(
+                contract
+                    .get_captcha_solution_commitment(solution_id)
+                    .unwrap()
+                    .status,
+                CaptchaStatus::Disapproved
+            );
+            let user = contract.get_dapp_user(dapp_user_account).unwrap();
+            assert_eq!(user.correct_captchas, 0);
+            assert_eq!(user.incorrect_captchas, 1);
+            assert_eq!(contract.get_provider_balance(provider_account), 100);
+            let stats = contract.get_provider_stats(provider_account).unwrap();
+            assert_eq!(stats.disputes_lost, 0);
+
+            // once the contract can pay, the same operator's vote upholds the dispute
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(
+                contract_account,
+                contract_balance,
+            );
+            let claimant_initial_balance = ink_env::test::get_account_balance::<
+                ink_env::DefaultEnvironment,
+            >(dapp_user_account)
+            .unwrap();
+            contract.dispute_vote(dispute_id, true).unwrap();
+            assert_eq!(
+                contract.get_dispute(dispute_id).unwrap().status,
+                DisputeStatus::Upheld
+            );
+            assert_eq!(
+                contract
+                    .get_captcha_solution_commitment(solution_id)
+                    .unwrap()
+                    .status,
+                CaptchaStatus::Approved
+            );
+            assert_eq!(contract.get_provider_balance(provider_account), 90);
+            assert_eq!(
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(
+                    dapp_user_account
+                )
+                .unwrap(),
+                claimant_initial_balance + 10
+            );
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let decoded_event = <Event as scale::Decode>::decode(
+                &mut &emitted_events[emitted_events.len() - 3].data[..],
+            )
+            .expect("encountered invalid contract event data buffer");
+            if let Event::ProviderSlashed(ProviderSlashed {
+                account,
+                value,
+                beneficiary,
+            }) = decoded_event
+            {
+                assert_eq!(account, provider_account);
+                assert_eq!(value, 10);
+                assert_eq!(beneficiary, Some(dapp_user_account));
+            } else {
+                panic!("encountered unexpected event kind: expected a ProviderSlashed event");
+            }
 
-            (provider_account, service_origin, fee)
+            // the closed dispute cannot flip the verdict back
+            advance_blocks(DEFAULT_DISPUTE_VOTING_PERIOD + 1);
+            assert_eq!(
+                Error::DisputeClosed,
+                contract.dispute_close(dispute_id).unwrap_err()
+            );
         }
 
-        /// Test add operator
+        /// Test operator slash provider including unbonding stake
         #[ink::test]
-        fn test_add_operator() {
+        fn test_operator_slash_provider() {
             let operator_account = AccountId::from([0x1; 32]);
             let mut contract = Prosopo::default(operator_account);
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            contract.provider_unstake(95).unwrap();
+
+            // only operators can slash
+            assert_eq!(
+                Error::NotAuthorised,
+                contract
+                    .operator_slash_provider(provider_account, None)
+                    .unwrap_err()
+            );
+
+            // 10% of the 100 staked is 10, 5 from the balance and 5 from the unbonding stake
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            let slashed = contract
+                .operator_slash_provider(provider_account, None)
+                .unwrap();
+            assert_eq!(slashed, 10);
+            assert_eq!(contract.get_treasury_balance(), 10);
+            assert_eq!(contract.get_provider_balance(provider_account), 0);
+            assert_eq!(
+                contract.get_provider_unbonding(provider_account)[0].value,
+                90
+            );
+            assert_eq!(
+                contract.providers.get(&provider_account).unwrap().status,
+                GovernanceStatus::Suspended
+            );
+
+            // operators pay the slashed stake out of the treasury
+            let recipient = AccountId::from([0x6; 32]);
+            let recipient_initial_balance =
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(recipient)
+                    .unwrap();
+            assert_eq!(
+                Error::TreasuryInsufficientFunds,
+                contract
+                    .operator_withdraw_treasury(recipient, 11)
+                    .unwrap_err()
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            assert_eq!(
+                Error::NotAuthorised,
+                contract
+                    .operator_withdraw_treasury(provider_account, 4)
+                    .unwrap_err()
+            );
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
-            let operator_account_new = AccountId::from([0x2; 32]);
-            contract.add_prosopo_operator(operator_account_new);
-            assert!(contract.operator_accounts.contains(&operator_account_new));
-            assert!(contract.operators.get(&operator_account_new).is_some());
+            contract.operator_withdraw_treasury(recipient, 4).unwrap();
+            assert_eq!(contract.get_treasury_balance(), 6);
+            assert_eq!(
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(recipient)
+                    .unwrap(),
+                recipient_initial_balance + 4
+            );
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let decoded_event =
+                <Event as scale::Decode>::decode(&mut &emitted_events.last().unwrap().data[..])
+                    .expect("encountered invalid contract event data buffer");
+            if let Event::TreasuryWithdraw(TreasuryWithdraw {
+                operator,
+                to,
+                value,
+            }) = decoded_event
+            {
+                assert_eq!(operator, operator_account);
+                assert_eq!(to, recipient);
+                assert_eq!(value, 4);
+            } else {
+                panic!("encountered unexpected event kind: expected a TreasuryWithdraw event");
+            }
         }
 
-        /// Test provider register and update
+        /// Test dapp user records are kept per dapp as well as globally
         #[ink::test]
-        fn test_provider_register_and_update() {
+        fn test_get_dapp_user_for_dapp() {
             let operator_account = AccountId::from([0x1; 32]);
             let mut contract = Prosopo::default(operator_account);
-            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "2424", 0);
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
             contract
                 .provider_register(service_origin, fee, Payee::Provider, provider_account)
                 .unwrap();
-            assert!(contract.providers.get(&provider_account).is_some());
-            assert!(contract
-                .provider_accounts
-                .get(GovernanceStatus::Deactivated)
-                .unwrap()
-                .contains(&provider_account));
-            let service_origin = str_to_hash("https://localhost:4242".to_string());
-            let fee: u32 = 100;
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
-            let balance = 1000;
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
-            contract.provider_update(service_origin, fee, Payee::Dapp, provider_account);
-            assert!(contract
-                .provider_accounts
-                .get(GovernanceStatus::Active)
-                .unwrap()
-                .contains(&provider_account));
-            let provider = contract.providers.get(&provider_account).unwrap();
-            assert_eq!(provider.service_origin, service_origin);
-            assert_eq!(provider.fee, fee);
-            assert_eq!(provider.payee, Payee::Dapp);
-            assert_eq!(provider.balance, balance);
-            assert_eq!(provider.status, GovernanceStatus::Active);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
 
-            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            // Register and fund the dapp, and verify it from the dapp contract
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
 
-            // first event is the register event, second event is the update
-            assert_eq!(2, emitted_events.len());
+            // Commit a solution as the dapp user
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let solution_id = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                .unwrap();
 
-            let event_provider_update = &emitted_events[1];
+            // register a second dapp
+            let other_dapp_contract_account = AccountId::from([0x8; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(AccountId::from([0x3; 32]));
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:4343".to_string());
+            contract.dapp_register(client_origin, other_dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(other_dapp_contract_account);
+            contract.dapp_verify(AccountId::from([0x3; 32])).unwrap();
 
-            let decoded_event_update =
-                <Event as scale::Decode>::decode(&mut &event_provider_update.data[..])
-                    .expect("encountered invalid contract event data buffer");
+            // the user has no record for a dapp until a verdict is given
+            assert_eq!(
+                Error::DappUserDoesNotExist,
+                contract
+                    .get_dapp_user_for_dapp(dapp_user_account, dapp_contract_account)
+                    .unwrap_err()
+            );
 
-            if let Event::ProviderUpdate(ProviderUpdate { account }) = decoded_event_update {
-                assert_eq!(
-                    account, provider_account,
-                    "encountered invalid ProviderUpdate.account"
-                );
-            } else {
-                panic!("encountered unexpected event kind: expected a ProviderUpdate event");
-            }
+            // the user solves a captcha for the second dapp incorrectly
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
+            let other_solution_id = str_to_hash("other user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(
+                    other_dapp_contract_account,
+                    root,
+                    other_solution_id,
+                    provider_account,
+                )
+                .unwrap();
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            contract.provider_approve(solution_id, 0).unwrap();
+            contract.provider_disapprove(other_solution_id).unwrap();
+
+            let user = contract.get_dapp_user(dapp_user_account).unwrap();
+            assert_eq!(user.correct_captchas, 1);
+            assert_eq!(user.incorrect_captchas, 1);
+            let dapp_record = contract
+                .get_dapp_user_for_dapp(dapp_user_account, dapp_contract_account)
+                .unwrap();
+            assert_eq!(dapp_record.correct_captchas, 1);
+            assert_eq!(dapp_record.incorrect_captchas, 0);
+            let other_dapp_record = contract
+                .get_dapp_user_for_dapp(dapp_user_account, other_dapp_contract_account)
+                .unwrap();
+            assert_eq!(other_dapp_record.correct_captchas, 0);
+            assert_eq!(other_dapp_record.incorrect_captchas, 1);
         }
 
-        /// Test provider register with service_origin error
+        /// Test dapp user is human
         #[ink::test]
-        fn test_provider_register_with_service_origin_error() {
+        // TODO probably should change the name of this function in the main contract
+        fn test_dapp_operator_is_human_user() {
             let operator_account = AccountId::from([0x1; 32]);
+
+            // initialise the contract
             let mut contract = Prosopo::default(operator_account);
 
+            // Register the provider
             let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
-
             contract
                 .provider_register(service_origin, fee, Payee::Provider, provider_account)
                 .unwrap();
 
-            // try creating the second provider and make sure the error is correct and that it doesn't exist
-            let (provider_account, _, _) = generate_provider_data(0x3, "4242", 0);
-            match contract.provider_register(service_origin, fee, Payee::Provider, provider_account)
-            {
-                Result::Err(Error::ProviderServiceOriginUsed) => {
-                    assert!(true);
-                }
-                _ => {
-                    assert!(false);
-                }
-            }
-            assert!(contract.providers.get(&provider_account).is_none());
-            assert!(!contract
-                .provider_accounts
-                .get(GovernanceStatus::Deactivated)
-                .unwrap()
-                .contains(&provider_account));
+            // Call from the provider account to add data and stake tokens
+            let balance = 100;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            let root = str_to_hash("merkle tree root".to_string());
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            contract.provider_update(service_origin, fee, Payee::Provider, provider_account);
+            // can only add data set after staking
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .ok();
+
+            // Register the dapp
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+
+            // Call from the dapp account
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            // Give the dap a balance
+            let balance = 100;
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            let client_origin = service_origin.clone();
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+
+            // Call from the dapp contract to verify the registration
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+
+            //Dapp User commit
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            // Call from the Dapp User Account
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let user_root = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, user_root, provider_account)
+                .ok();
+
+            // Call from the provider account to mark the solution as disapproved
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            let solution_id = user_root;
+            contract.provider_disapprove(solution_id);
+            let commitment = contract
+                .captcha_solution_commitments
+                .get(&solution_id)
+                .unwrap();
+            assert_eq!(commitment.status, CaptchaStatus::Disapproved);
+
+            // Now make sure that the dapp user does not pass the human test
+            let result = contract
+                .dapp_operator_is_human_user(dapp_user_account, 80)
+                .unwrap();
+            assert_eq!(result, false);
         }
 
-        /// Test provider update with service_origin error
+        /// Test dapp human verification policy
         #[ink::test]
-        fn test_provider_update_with_service_origin_error() {
+        fn test_dapp_is_human() {
             let operator_account = AccountId::from([0x1; 32]);
             let mut contract = Prosopo::default(operator_account);
-
+            // Register the provider and stake tokens with a dataset
             let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
-
             contract
                 .provider_register(service_origin, fee, Payee::Provider, provider_account)
                 .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
 
-            let (provider_account, service_origin, fee) = generate_provider_data(0x3, "2424", 0);
+            // Register and fund the dapp, and verify it from the dapp contract
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
 
+            // Commit a solution as the dapp user
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let solution_id = str_to_hash("user merkle tree root".to_string());
             contract
-                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
                 .unwrap();
+            let dapp_owner = AccountId::from([0x3; 32]);
 
-            let (_, service_origin, fee) = generate_provider_data(0x3, "4242", 100);
+            // the user has not solved any captchas yet
+            assert!(!contract
+                .dapp_is_human(dapp_contract_account, dapp_user_account)
+                .unwrap());
 
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
-            let balance = 1000;
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            contract.provider_approve(solution_id, 0).unwrap();
+            assert!(contract
+                .dapp_is_human(dapp_contract_account, dapp_user_account)
+                .unwrap());
 
-            // try updating the second provider and make sure the error is correct and that it didn't change
-            match contract.provider_update(service_origin, fee, Payee::Dapp, provider_account) {
-                Result::Err(Error::ProviderServiceOriginUsed) => {
-                    assert!(true);
-                }
-                _ => {
-                    assert!(false);
-                }
-            }
+            // one correct solution of two is below the default 80% threshold
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let other_solution_id = str_to_hash("other user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(
+                    dapp_contract_account,
+                    root,
+                    other_solution_id,
+                    provider_account,
+                )
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            contract.provider_disapprove(other_solution_id).unwrap();
+            assert!(!contract
+                .dapp_is_human(dapp_contract_account, dapp_user_account)
+                .unwrap());
+
+            // the dapp owner can relax the policy
+            let policy = HumanPolicy {
+                min_captchas: 2,
+                min_correct_bps: 5_000,
+                max_last_correct_age: 0,
+            };
+            assert_eq!(
+                Error::NotAuthorised,
+                contract
+                    .dapp_set_human_policy(dapp_contract_account, policy)
+                    .unwrap_err()
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_owner);
+            contract
+                .dapp_set_human_policy(dapp_contract_account, policy)
+                .unwrap();
+            assert!(contract
+                .dapp_is_human(dapp_contract_account, dapp_user_account)
+                .unwrap());
 
-            let provider = contract.providers.get(&provider_account).unwrap();
-            assert_ne!(provider.service_origin, service_origin);
-            assert_ne!(provider.fee, fee);
-            assert_ne!(provider.balance, balance);
-            assert_ne!(provider.status, GovernanceStatus::Active);
+            // out of bounds policies are rejected
+            let invalid_policy = HumanPolicy {
+                min_correct_bps: 10_001,
+                ..policy
+            };
+            assert_eq!(
+                Error::InvalidHumanPolicy,
+                contract
+                    .dapp_set_human_policy(dapp_contract_account, invalid_policy)
+                    .unwrap_err()
+            );
         }
 
-        /// Test provider unstake
+        /// Test dapp owners can withdraw funds that are not reserved for pending commitments
         #[ink::test]
-        fn test_provider_unstake() {
+        fn test_dapp_withdraw() {
             let operator_account = AccountId::from([0x1; 32]);
             let mut contract = Prosopo::default(operator_account);
-            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
-            let balance: u128 = 10;
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 10);
             contract
                 .provider_register(service_origin, fee, Payee::Provider, provider_account)
-                .ok();
-            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(
-                provider_account,
-                balance,
-            );
+                .unwrap();
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
-            contract.provider_update(service_origin, fee, Payee::Provider, provider_account);
-            contract.provider_unstake().ok();
-            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
 
-            // events are the register event (0), stake event(1), deregister(2) and the unstake event(3)
+            // Register and fund the dapp, and verify it from the dapp contract
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
 
-            assert_eq!(4, emitted_events.len());
+            // Commit a solution as the dapp user
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let solution_id = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                .unwrap();
+            let dapp_owner = AccountId::from([0x3; 32]);
 
-            let event_unstake = &emitted_events[3];
-            let decoded_event_unstake =
-                <Event as scale::Decode>::decode(&mut &event_unstake.data[..])
-                    .expect("encountered invalid contract event data buffer");
+            // the provider's fee is reserved while the commitment is pending
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert_eq!(dapp.balance, 100);
+            assert_eq!(dapp.reserved, 10);
 
-            if let Event::ProviderUnstake(ProviderUnstake { account, value }) =
-                decoded_event_unstake
-            {
-                assert_eq!(
-                    account, provider_account,
-                    "encountered invalid ProviderUnstake.account"
-                );
-                assert_eq!(value, balance, "encountered invalid ProviderUnstake.value");
+            // only the owner can withdraw
+            assert_eq!(
+                Error::NotAuthorised,
+                contract
+                    .dapp_withdraw(dapp_contract_account, 10)
+                    .unwrap_err()
+            );
+
+            // reserved funds cannot be withdrawn
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_owner);
+            assert_eq!(
+                Error::DappInsufficientFunds,
+                contract
+                    .dapp_withdraw(dapp_contract_account, 91)
+                    .unwrap_err()
+            );
+
+            let owner_initial_balance =
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(dapp_owner)
+                    .unwrap();
+            contract.dapp_withdraw(dapp_contract_account, 50).unwrap();
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let decoded_event = <Event as scale::Decode>::decode(
+                &mut &emitted_events[emitted_events.len() - 1].data[..],
+            )
+            .expect("encountered invalid contract event data buffer");
+            if let Event::DappWithdraw(DappWithdraw {
+                contract: event_contract,
+                value,
+            }) = decoded_event
+            {
+                assert_eq!(event_contract, dapp_contract_account);
+                assert_eq!(value, 50);
             } else {
-                panic!("encountered unexpected event kind: expected a ProviderUnstake event");
+                panic!("encountered unexpected event kind: expected a DappWithdraw event");
             }
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert_eq!(dapp.balance, 50);
+            assert_eq!(dapp.status, GovernanceStatus::Active);
+            assert_eq!(
+                owner_initial_balance + 50,
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(dapp_owner)
+                    .unwrap()
+            );
+
+            // a fee raised while the commitment is pending is not charged to the dapp
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
+            let service_origin = str_to_hash("https://localhost:4242".to_string());
+            contract
+                .provider_update(service_origin, 60, Payee::Provider, provider_account)
+                .unwrap();
+
+            // the reservation is released once the provider gives a verdict
+            let provider_initial_balance = contract.get_provider_balance(provider_account);
+            contract.provider_approve(solution_id, 0).unwrap();
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert_eq!(dapp.balance, 40);
+            assert_eq!(dapp.reserved, 0);
+            assert_eq!(
+                provider_initial_balance + 10,
+                contract.get_provider_balance(provider_account)
+            );
+
+            // withdrawing the whole balance suspends the dapp
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_owner);
+            contract.dapp_withdraw(dapp_contract_account, 40).unwrap();
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert_eq!(dapp.balance, 0);
+            assert_eq!(dapp.status, GovernanceStatus::Suspended);
         }
 
-        /// Test provider add data set
+        /// Test resubmitted solution commitments are reported and cannot be taken over
         #[ink::test]
-        //TODO off-chain environment does not yet support `block_timestamp`
-        fn test_provider_add_dataset() {
+        fn test_dapp_user_commit_outcome() {
             let operator_account = AccountId::from([0x1; 32]);
             let mut contract = Prosopo::default(operator_account);
+            // Register the provider and stake tokens with a dataset
             let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
-            let balance: u128 = 10;
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
             contract
                 .provider_register(service_origin, fee, Payee::Provider, provider_account)
-                .ok();
-            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(
-                provider_account,
-                balance,
-            );
+                .unwrap();
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
-            contract.provider_update(service_origin, fee, Payee::Provider, provider_account);
-            let root = str_to_hash("merkle tree".to_string());
-            contract.provider_add_dataset(root).ok();
-            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
 
-            // events are the register, stake, add data set
-            assert_eq!(3, emitted_events.len());
+            // Register and fund the dapp, and verify it from the dapp contract
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
 
-            let event_unstake = &emitted_events[2];
-            let decoded_event_unstake =
-                <Event as scale::Decode>::decode(&mut &event_unstake.data[..])
-                    .expect("encountered invalid contract event data buffer");
+            // Commit a solution as the dapp user
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let solution_id = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                .unwrap();
 
-            if let Event::ProviderAddDataset(ProviderAddDataset {
-                account,
-                merkle_tree_root,
-            }) = decoded_event_unstake
-            {
-                assert_eq!(
-                    account, provider_account,
-                    "encountered invalid ProviderAddDataset.account"
-                );
-                assert_eq!(
-                    merkle_tree_root, root,
-                    "encountered invalid ProviderAddDataset.merkle_tree_root"
-                );
-            } else {
-                panic!("encountered unexpected event kind: expected a ProviderAddDataset event");
-            }
+            assert_eq!(
+                contract
+                    .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                    .unwrap(),
+                CommitmentOutcome::AlreadyExists
+            );
+            let other_solution_id = str_to_hash("other user merkle tree root".to_string());
+            assert_eq!(
+                contract
+                    .dapp_user_commit(
+                        dapp_contract_account,
+                        root,
+                        other_solution_id,
+                        provider_account
+                    )
+                    .unwrap(),
+                CommitmentOutcome::Created
+            );
+
+            // another account cannot reuse the user's root
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(AccountId::from([0x6; 32]));
+            assert_eq!(
+                Error::CaptchaSolutionCommitmentExists,
+                contract
+                    .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                    .unwrap_err()
+            );
+            assert_eq!(
+                contract
+                    .get_captcha_solution_commitment(solution_id)
+                    .unwrap()
+                    .account,
+                dapp_user_account
+            );
         }
 
-        /// Test provider cannot add data set if inactive
+        /// Test solutions can be revealed and unrevealed commitments are failed
         #[ink::test]
-        fn test_provider_cannot_add_dataset_if_inactive() {
+        fn test_commitment_reveal() {
             let operator_account = AccountId::from([0x1; 32]);
             let mut contract = Prosopo::default(operator_account);
-            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
-            let balance: u128 = 10;
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            let mut config = contract.get_protocol_config();
+            config.reveal_window = 2;
+            contract.operator_set_protocol_config(config).unwrap();
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 10);
             contract
                 .provider_register(service_origin, fee, Payee::Provider, provider_account)
-                .ok();
-            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(
-                provider_account,
-                balance,
-            );
+                .unwrap();
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
-            let root = str_to_hash("merkle tree".to_string());
-            let result = contract.provider_add_dataset(root).unwrap_err();
-            assert_eq!(ProviderInactive, result)
-        }
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
 
-        /// Test dapp register with zero balance transfer
-        #[ink::test]
-        fn test_dapp_register_zero_balance_transfer() {
-            let operator_account = AccountId::from([0x1; 32]);
-            let mut contract = Prosopo::default(operator_account);
-            let caller = AccountId::from([0x2; 32]);
-            let dapp_contract = AccountId::from([0x3; 32]);
-            // Call from the dapp account
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(caller);
-            // Don't transfer anything with the call
-            let balance = 0;
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            // Register and fund the dapp, and verify it from the dapp contract
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
             let client_origin = str_to_hash("https://localhost:2424".to_string());
-            contract.dapp_register(client_origin, dapp_contract, None);
-            assert!(contract.dapps.get(&dapp_contract).is_some());
-            let dapp = contract.dapps.get(&dapp_contract).unwrap();
-            assert_eq!(dapp.owner, caller);
-            assert_eq!(dapp.client_origin, client_origin);
-
-            // account is marked as suspended as zero tokens have been paid
-            assert_eq!(dapp.status, GovernanceStatus::Suspended);
-            assert_eq!(dapp.balance, balance);
-            assert!(contract.dapp_accounts.contains(&dapp_contract));
-        }
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
 
-        /// Test dapp register with positive balance transfer
-        #[ink::test]
-        fn test_dapp_register_positive_balance_transfer() {
-            let operator_account = AccountId::from([0x1; 32]);
-            let mut contract = Prosopo::default(operator_account);
-            let caller = AccountId::from([0x2; 32]);
-            let dapp_contract = AccountId::from([0x3; 32]);
-            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            // Commit a solution as the dapp user
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let solution_id = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                .unwrap();
+            let dapp_owner = AccountId::from([0x3; 32]);
 
-            // Call from the dapp account
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(caller);
+            // commitments made before reveals are required do not need one
+            assert_eq!(
+                Error::SolutionRevealNotRequired,
+                contract
+                    .commitment_reveal(solution_id, Vec::new(), Hash::default())
+                    .unwrap_err()
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_owner);
+            contract
+                .dapp_set_solution_reveal(dapp_contract_account, true)
+                .unwrap();
+            contract
+                .dapp_set_refund_reserve(dapp_contract_account, 5)
+                .unwrap();
 
-            // Transfer tokens with the call
-            let balance = 100;
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            let solutions = vec![
+                (
+                    str_to_hash("captcha a".to_string()),
+                    str_to_hash("solution a".to_string()),
+                ),
+                (
+                    str_to_hash("captcha b".to_string()),
+                    str_to_hash("solution b".to_string()),
+                ),
+                (
+                    str_to_hash("captcha c".to_string()),
+                    str_to_hash("solution c".to_string()),
+                ),
+            ];
+            let salt = str_to_hash("salt".to_string());
+            let leaves: Vec<Hash> = solutions
+                .iter()
+                .map(|(captcha_id, solution)| Prosopo::merkle_leaf(captcha_id, solution))
+                .collect();
+            let revealed_id =
+                Prosopo::merkle_salted_root(&Prosopo::merkle_root(leaves.clone()), &salt);
+            let unrevealed_id = str_to_hash("unrevealed user merkle tree root".to_string());
+            let disputed_id = str_to_hash("disputed user merkle tree root".to_string());
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            for commitment_id in [revealed_id, unrevealed_id, disputed_id] {
+                contract
+                    .dapp_user_commit(dapp_contract_account, root, commitment_id, provider_account)
+                    .unwrap();
+            }
 
-            // register the dapp
-            contract.dapp_register(client_origin, dapp_contract, None);
-            // check the dapp exists in the hashmap
-            assert!(contract.dapps.get(&dapp_contract).is_some());
+            // the solutions must hash to the committed root
+            assert_eq!(
+                Error::InvalidSolutionReveal,
+                contract
+                    .commitment_reveal(revealed_id, solutions.clone(), Hash::default())
+                    .unwrap_err()
+            );
+            // repeating the last solution changes the root
+            let mut duplicated = solutions.clone();
+            duplicated.push(solutions[2]);
+            assert_eq!(
+                Error::InvalidSolutionReveal,
+                contract
+                    .commitment_reveal(revealed_id, duplicated, salt)
+                    .unwrap_err()
+            );
+            contract
+                .commitment_reveal(revealed_id, solutions.clone(), salt)
+                .unwrap();
+            assert_eq!(contract.get_commitment_solutions(revealed_id), solutions);
+
+            // the revealed solutions can be proven against the same salted root
+            let proof = CaptchaSolutionProof {
+                captcha_id: solutions[0].0,
+                solution: solutions[0].1,
+                commitment_proof: vec![
+                    [leaves[0], leaves[1]],
+                    [Prosopo::merkle_hash_pair(&leaves[0], &leaves[1]), leaves[2]],
+                ],
+                dataset_proof: Vec::new(),
+            };
+            assert_eq!(
+                contract
+                    .verify_captcha_solutions(revealed_id, salt, vec![proof])
+                    .unwrap(),
+                vec![false]
+            );
 
-            // check the various attributes are correct
-            let dapp = contract.dapps.get(&dapp_contract).unwrap();
-            assert_eq!(dapp.owner, caller);
-            assert_eq!(dapp.client_origin, client_origin);
+            // unrevealed commitments can only be failed once the reveal window has closed
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            let user_initial_balance = ink_env::test::get_account_balance::<
+                ink_env::DefaultEnvironment,
+            >(dapp_user_account)
+            .unwrap();
+            contract.provider_approve(unrevealed_id, 5).unwrap();
+            contract.provider_approve(disputed_id, 0).unwrap();
+            // the dapp paid the fee of 10 for each approval and refunded 5 for one of them
+            assert_eq!(contract.get_provider_balance(provider_account), 120);
+            assert_eq!(contract.get_dapp_balance(dapp_contract_account), 75);
+            assert_eq!(
+                Error::SolutionRevealWindowOpen,
+                contract
+                    .commitment_finalise_unrevealed(unrevealed_id)
+                    .unwrap_err()
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_owner);
+            contract.dispute_open(disputed_id).unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            advance_blocks(3);
+            assert_eq!(
+                Error::SolutionRevealWindowClosed,
+                contract
+                    .commitment_reveal(unrevealed_id, solutions, salt)
+                    .unwrap_err()
+            );
+            contract
+                .commitment_finalise_unrevealed(unrevealed_id)
+                .unwrap();
+            let commitment = contract
+                .get_captcha_solution_commitment(unrevealed_id)
+                .unwrap();
+            assert_eq!(commitment.status, CaptchaStatus::Disapproved);
+            assert_eq!(commitment.reveal, SolutionReveal::Missed);
+            // the provider repays the dapp the fee and refund it paid for the approval, while the
+            // user keeps their refund
+            assert_eq!(contract.get_provider_balance(provider_account), 105);
+            assert_eq!(contract.get_dapp_balance(dapp_contract_account), 90);
+            assert_eq!(
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(
+                    dapp_user_account
+                )
+                .unwrap(),
+                user_initial_balance + 5
+            );
+            let user = contract
+                .get_dapp_user_for_dapp(dapp_user_account, dapp_contract_account)
+                .unwrap();
+            assert_eq!(user.correct_captchas, 1);
+            assert_eq!(user.incorrect_captchas, 1);
+            assert_eq!(
+                Error::SolutionRevealNotRequired,
+                contract
+                    .commitment_finalise_unrevealed(revealed_id)
+                    .unwrap_err()
+            );
 
-            // account is marked as active as balance is now positive
-            assert_eq!(dapp.status, GovernanceStatus::Active);
-            assert_eq!(dapp.balance, balance);
-            assert!(contract.dapp_accounts.contains(&dapp_contract));
+            // a commitment failed by a missed reveal cannot be disputed
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            assert_eq!(
+                Error::SolutionRevealMissed,
+                contract.dispute_open(unrevealed_id).unwrap_err()
+            );
+            // and a commitment whose verdict is disputed cannot be failed until the dispute closes
+            assert_eq!(
+                Error::DisputeExists,
+                contract
+                    .commitment_finalise_unrevealed(disputed_id)
+                    .unwrap_err()
+            );
         }
 
-        /// Test dapp register and then update
+        /// Test revealed solutions are verified against the user's commitment and the dataset
         #[ink::test]
-        //TODO fix tests so that register and update are separate
-        fn test_dapp_register_and_update() {
-            // TODO fix this test so that contract is actually updated, currently a new one is being
-            //   created
+        fn test_verify_captcha_solutions() {
             let operator_account = AccountId::from([0x1; 32]);
             let mut contract = Prosopo::default(operator_account);
-            let caller = AccountId::from([0x2; 32]);
-            let dapp_contract_account = AccountId::from([0x3; 32]);
-            let client_origin_1 = str_to_hash("https://localhost:2424".to_string());
-
-            // Call from the dapp account
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(caller);
-
-            // Transfer tokens with the call
-            let balance_1 = 100;
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance_1);
-
-            // register the dapp
-            contract.dapp_register(client_origin_1, dapp_contract_account, None);
-
-            // check the dapp exists in the hashmap
-            assert!(contract.dapps.get(&dapp_contract_account).is_some());
-
-            // check the various attributes are correct
-            let dapp = contract.dapps.get(&dapp_contract_account).unwrap();
-            assert_eq!(dapp.owner, caller);
-            assert_eq!(dapp.client_origin, client_origin_1);
-
-            // account is marked as active as tokens have been paid
-            assert_eq!(dapp.status, GovernanceStatus::Active);
-            assert_eq!(dapp.balance, balance_1);
-
-            // Transfer tokens with the call
-            let balance_2 = 200;
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance_2);
-
-            // run the register function again for the same (caller, contract) pair, adding more
-            // tokens and changing the client origin
-            let client_origin_2 = str_to_hash("https://localhost:2424".to_string()); // Implements `scale::Encode`
-            let new_owner = AccountId::from([0x5; 32]);
-            contract.dapp_register(client_origin_2, dapp_contract_account, Some(new_owner));
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
 
-            // check the various attributes are correct
-            let dapp = contract.dapps.get(&dapp_contract_account).unwrap();
-            assert_eq!(dapp.owner, new_owner);
-            assert_eq!(dapp.client_origin, client_origin_2);
+            // Register and fund the dapp, and verify it from the dapp contract
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
 
-            // account is marked as active as tokens have been paid
-            assert_eq!(dapp.status, GovernanceStatus::Active);
-            assert_eq!(dapp.balance, balance_1 + balance_2);
-            assert!(contract.dapp_accounts.contains(&dapp_contract_account));
-        }
+            let dapp_user_account = AccountId::from([0x5; 32]);
 
-        /// Test dapp fund account
-        #[ink::test]
-        fn test_dapp_fund() {
-            let operator_account = AccountId::from([0x1; 32]);
-            let mut contract = Prosopo::default(operator_account);
-            let caller = AccountId::from([0x2; 32]);
-            let dapp_contract = AccountId::from([0x3; 32]);
-            let client_origin_1 = str_to_hash("https://localhost:2424".to_string());
+            // a dataset of two captchas and a user who solves only the first correctly
+            let captcha_a = str_to_hash("captcha a".to_string());
+            let captcha_b = str_to_hash("captcha b".to_string());
+            let solution_a = str_to_hash("solution a".to_string());
+            let solution_b = str_to_hash("solution b".to_string());
+            let wrong_solution_b = str_to_hash("wrong solution b".to_string());
+            let dataset_leaves = [
+                Prosopo::merkle_leaf(&captcha_a, &solution_a),
+                Prosopo::merkle_leaf(&captcha_b, &solution_b),
+            ];
+            let dataset_root = Prosopo::merkle_hash_pair(&dataset_leaves[0], &dataset_leaves[1]);
+            let user_leaves = [
+                Prosopo::merkle_leaf(&captcha_a, &solution_a),
+                Prosopo::merkle_leaf(&captcha_b, &wrong_solution_b),
+            ];
+            let salt = str_to_hash("salt".to_string());
+            let user_root = Prosopo::merkle_salted_root(
+                &Prosopo::merkle_hash_pair(&user_leaves[0], &user_leaves[1]),
+                &salt,
+            );
 
-            // Call from the dapp account
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(caller);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            contract
+                .provider_add_dataset(
+                    dataset_root,
+                    CaptchaType::ImageSelection,
+                    2,
+                    1,
+                    dataset_root,
+                )
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            contract
+                .dapp_user_commit(
+                    dapp_contract_account,
+                    dataset_root,
+                    user_root,
+                    provider_account,
+                )
+                .unwrap();
 
-            // Transfer tokens with the register call
-            let balance_1 = 100;
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance_1);
+            let proof_a = CaptchaSolutionProof {
+                captcha_id: captcha_a,
+                solution: solution_a,
+                commitment_proof: vec![user_leaves],
+                dataset_proof: vec![dataset_leaves],
+            };
+            let proof_b = CaptchaSolutionProof {
+                captcha_id: captcha_b,
+                solution: wrong_solution_b,
+                commitment_proof: vec![user_leaves],
+                dataset_proof: vec![dataset_leaves],
+            };
+            assert_eq!(
+                contract
+                    .verify_captcha_solutions(user_root, salt, vec![proof_a.clone(), proof_b])
+                    .unwrap(),
+                vec![true, false]
+            );
 
-            // register the dapp
-            contract.dapp_register(client_origin_1, dapp_contract, None);
+            // the proofs must lead to the root the user salted
+            assert_eq!(
+                Error::InvalidSolutionCommitmentProof,
+                contract
+                    .verify_captcha_solutions(user_root, Hash::default(), vec![proof_a.clone()])
+                    .unwrap_err()
+            );
 
-            // Transfer tokens with the fund call
-            let balance_2 = 200;
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance_2);
-            contract.dapp_fund(dapp_contract);
+            // solutions the user did not commit to are rejected
+            let uncommitted_proof = CaptchaSolutionProof {
+                captcha_id: captcha_b,
+                solution: solution_b,
+                ..proof_a
+            };
+            assert_eq!(
+                Error::InvalidSolutionCommitmentProof,
+                contract
+                    .verify_captcha_solutions(user_root, salt, vec![uncommitted_proof])
+                    .unwrap_err()
+            );
 
-            // check the total account balance is correct
-            let dapp = contract.dapps.get(&dapp_contract).unwrap();
-            assert_eq!(dapp.balance, balance_1 + balance_2);
+            // a dataset node presented as a captcha and its solution is not a dataset leaf
+            let forged_root = Prosopo::merkle_salted_root(
+                &Prosopo::merkle_leaf(&dataset_leaves[0], &dataset_leaves[1]),
+                &salt,
+            );
+            contract
+                .dapp_user_commit(
+                    dapp_contract_account,
+                    dataset_root,
+                    forged_root,
+                    provider_account,
+                )
+                .unwrap();
+            let forged_proof = CaptchaSolutionProof {
+                captcha_id: dataset_leaves[0],
+                solution: dataset_leaves[1],
+                commitment_proof: Vec::new(),
+                dataset_proof: Vec::new(),
+            };
+            assert_eq!(
+                contract
+                    .verify_captcha_solutions(forged_root, salt, vec![forged_proof])
+                    .unwrap(),
+                vec![false]
+            );
         }
 
-        /// Test dapp cancel
+        /// Test providers can submit verdicts in a batch
         #[ink::test]
-        fn test_dapp_cancel() {
+        fn test_provider_submit_verdicts() {
             let operator_account = AccountId::from([0x1; 32]);
             let mut contract = Prosopo::default(operator_account);
-            let caller = AccountId::from([0x2; 32]);
-            let contract_account = AccountId::from([0x3; 32]);
-            let client_origin_1 = str_to_hash("https://localhost:2424".to_string());
-            let callers_initial_balance =
-                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(caller).unwrap();
-
-            // Call from the dapp account
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(caller);
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 10);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
 
-            // Transfer tokens with the register call
-            let balance = 100;
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            // Register and fund the dapp, and verify it from the dapp contract
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
 
-            // register the dapp
-            contract.dapp_register(client_origin_1, contract_account, None);
+            // Commit a solution as the dapp user
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let solution_id = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                .unwrap();
+            let other_solution_id = str_to_hash("other user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(
+                    dapp_contract_account,
+                    root,
+                    other_solution_id,
+                    provider_account,
+                )
+                .unwrap();
+            let provider_initial_balance = contract.get_provider_balance(provider_account);
 
-            // Transfer tokens with the fund call
-            contract.dapp_cancel(contract_account).ok();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            let results = contract
+                .provider_submit_verdicts(vec![
+                    (solution_id, Verdict::Approved, 0),
+                    (other_solution_id, Verdict::Disapproved, 0),
+                    (root, Verdict::Approved, 0),
+                ])
+                .unwrap();
+            assert_eq!(
+                results,
+                vec![
+                    Ok(()),
+                    Ok(()),
+                    Err(Error::CaptchaSolutionCommitmentDoesNotExist)
+                ]
+            );
+            assert_eq!(
+                contract
+                    .get_captcha_solution_commitment(solution_id)
+                    .unwrap()
+                    .status,
+                CaptchaStatus::Approved
+            );
+            assert_eq!(
+                contract
+                    .get_captcha_solution_commitment(other_solution_id)
+                    .unwrap()
+                    .status,
+                CaptchaStatus::Disapproved
+            );
 
-            // check the funds are returned and the dapp's status is Deactivated
-            let dapp = contract.dapps.get(&contract_account).unwrap();
-            assert_eq!(dapp.status, GovernanceStatus::Deactivated);
+            // the fees for both verdicts are settled
+            assert_eq!(
+                provider_initial_balance + 20,
+                contract.get_provider_balance(provider_account)
+            );
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert_eq!(dapp.balance, 80);
+            assert_eq!(dapp.reserved, 0);
+            let stats = contract.get_provider_stats(provider_account).unwrap();
+            assert_eq!(stats.approved, 1);
+            assert_eq!(stats.disapproved, 1);
+            let user = contract.get_dapp_user(dapp_user_account).unwrap();
+            assert_eq!(user.correct_captchas, 1);
+            assert_eq!(user.incorrect_captchas, 1);
+
+            // verdicts on decided commitments are reported and settle nothing
+            let results = contract
+                .provider_submit_verdicts(vec![
+                    (solution_id, Verdict::Disapproved, 0),
+                    (other_solution_id, Verdict::Approved, 0),
+                ])
+                .unwrap();
+            assert_eq!(
+                results,
+                vec![
+                    Err(Error::CaptchaSolutionCommitmentNotPending),
+                    Err(Error::CaptchaSolutionCommitmentNotPending)
+                ]
+            );
+            assert_eq!(
+                Error::CaptchaSolutionCommitmentNotPending,
+                contract.provider_approve(other_solution_id, 0).unwrap_err()
+            );
+            assert_eq!(
+                provider_initial_balance + 20,
+                contract.get_provider_balance(provider_account)
+            );
+            assert_eq!(
+                contract
+                    .get_dapp_details(dapp_contract_account)
+                    .unwrap()
+                    .balance,
+                80
+            );
 
-            // Make sure the funds are returned to the caller
-            assert_eq!(dapp.balance, 0);
-            let callers_balance =
-                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(caller).unwrap();
-            assert_eq!(callers_initial_balance + balance, callers_balance);
+            // only registered providers can submit verdicts
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            assert_eq!(
+                Error::ProviderDoesNotExist,
+                contract.provider_submit_verdicts(Vec::new()).unwrap_err()
+            );
         }
 
-        /// Test dapp user commit
-        /// A dapp user can only commit a solution to the chain when there is at least one captcha
-        /// provider and one dapp available.
+        /// Test block numbers and timestamps are recorded on commitments and dapp users
         #[ink::test]
-        fn test_dapp_user_commit() {
+        fn test_commitment_and_user_times() {
             let operator_account = AccountId::from([0x1; 32]);
-
-            // initialise the contract
             let mut contract = Prosopo::default(operator_account);
-
-            // Register the provider
-            let provider_account = AccountId::from([0x2; 32]);
-            let service_origin = str_to_hash("https://localhost:2424".to_string());
-            let fee: u32 = 0;
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
             contract
                 .provider_register(service_origin, fee, Payee::Provider, provider_account)
-                .ok();
-
-            // Call from the provider account to add data and stake tokens
-            let balance = 100;
+                .unwrap();
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
-            let root = str_to_hash("blah".to_string());
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
-            contract.provider_update(service_origin, fee, Payee::Provider, provider_account);
-            // can only add data set after staking
-            // TODO test scenario where dataset is added before staking
-            contract.provider_add_dataset(root).ok();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
 
-            // Register the dapp
+            // Register and fund the dapp, and verify it from the dapp contract
             let dapp_caller_account = AccountId::from([0x3; 32]);
             let dapp_contract_account = AccountId::from([0x4; 32]);
-
-            // Call from the dapp account
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
-            // Give the dap a balance
-            let balance = 100;
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
-            let client_origin = service_origin.clone();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
             contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
 
-            //Dapp User commit
-            let user_root = str_to_hash("user merkle tree root".to_string());
+            // Commit a solution as the dapp user
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let solution_id = str_to_hash("user merkle tree root".to_string());
             contract
-                .dapp_user_commit(dapp_contract_account, root, user_root, provider_account)
-                .ok();
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                .unwrap();
+            let commitment = contract
+                .get_captcha_solution_commitment(solution_id)
+                .unwrap();
+            let user = contract.get_dapp_user(dapp_user_account).unwrap();
+            assert_eq!(user.created, commitment.created_timestamp);
+            assert_eq!(user.updated, commitment.created_timestamp);
 
-            // check that the data is in the captcha_solution_commitments hashmap
-            assert!(contract
-                .captcha_solution_commitments
-                .get(&user_root)
-                .is_some());
+            advance_blocks(5);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            contract.provider_approve(solution_id, 0).unwrap();
+            let commitment = contract
+                .get_captcha_solution_commitment(solution_id)
+                .unwrap();
+            assert_eq!(commitment.verdict_block, commitment.created_block + 5);
+            assert!(commitment.verdict_timestamp > commitment.created_timestamp);
+
+            let user = contract.get_dapp_user(dapp_user_account).unwrap();
+            assert_eq!(user.updated, commitment.verdict_timestamp);
+            assert_eq!(user.last_correct_captcha_id, solution_id);
+            let dapp_record = contract
+                .get_dapp_user_for_dapp(dapp_user_account, dapp_contract_account)
+                .unwrap();
+            assert_eq!(dapp_record.created, commitment.verdict_timestamp);
+            assert_eq!(dapp_record.last_correct_captcha_id, solution_id);
         }
 
-        /// Test provider approve
+        /// Test commitments that do not receive a verdict in time can be expired by anyone
         #[ink::test]
-        // TODO move the common stuff to a setup function
-        fn test_provider_approve() {
+        fn test_commitment_expire() {
             let operator_account = AccountId::from([0x1; 32]);
-
-            // initialise the contract
             let mut contract = Prosopo::default(operator_account);
-
-            // Register the provider
-            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            let mut config = contract.get_protocol_config();
+            config.commitment_expiry = 2;
+            contract.operator_set_protocol_config(config).unwrap();
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 10);
             contract
                 .provider_register(service_origin, fee, Payee::Provider, provider_account)
                 .unwrap();
-
-            // Call from the provider account to add data and stake tokens
-            let balance = 100;
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
             let root = str_to_hash("merkle tree root".to_string());
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
-            contract.provider_update(service_origin, fee, Payee::Provider, provider_account);
-
-            let provider = contract.providers.get(&provider_account).unwrap();
-            // can only add data set after staking
-            contract.provider_add_dataset(root).ok();
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
 
-            // Register the dapp
+            // Register and fund the dapp, and verify it from the dapp contract
             let dapp_caller_account = AccountId::from([0x3; 32]);
             let dapp_contract_account = AccountId::from([0x4; 32]);
-
-            // Call from the dapp account
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
-            // Give the dap a balance
-            let balance = 100;
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
-            let client_origin = service_origin.clone();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
             contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
 
-            //Dapp User commit
+            // Commit a solution as the dapp user
             let dapp_user_account = AccountId::from([0x5; 32]);
-            let user_root = str_to_hash("user merkle tree root".to_string());
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let solution_id = str_to_hash("user merkle tree root".to_string());
             contract
-                .dapp_user_commit(dapp_contract_account, root, user_root, provider_account)
-                .ok();
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                .unwrap();
+            assert_eq!(
+                contract
+                    .get_dapp_details(dapp_contract_account)
+                    .unwrap()
+                    .reserved,
+                10
+            );
+            let provider_initial_balance = contract.get_provider_balance(provider_account);
 
-            // Call from the provider account to mark the solution as approved
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(AccountId::from([0x6; 32]));
+            assert_eq!(
+                Error::CaptchaSolutionCommitmentNotExpired,
+                contract.commitment_expire(solution_id).unwrap_err()
+            );
+            advance_blocks(3);
+            contract.commitment_expire(solution_id).unwrap();
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let decoded_event = <Event as scale::Decode>::decode(
+                &mut &emitted_events[emitted_events.len() - 1].data[..],
+            )
+            .expect("encountered invalid contract event data buffer");
+            if let Event::CommitmentExpire(CommitmentExpire {
+                captcha_solution_commitment_id,
+                provider,
+            }) = decoded_event
+            {
+                assert_eq!(captcha_solution_commitment_id, solution_id);
+                assert_eq!(provider, provider_account);
+            } else {
+                panic!("encountered unexpected event kind: expected a CommitmentExpire event");
+            }
+            assert_eq!(
+                contract
+                    .get_captcha_solution_commitment(solution_id)
+                    .unwrap()
+                    .status,
+                CaptchaStatus::Expired
+            );
+            assert_eq!(
+                Error::CaptchaSolutionCommitmentNotPending,
+                contract.commitment_expire(solution_id).unwrap_err()
+            );
+
+            // the dapp's reserved funds are released unpaid and the provider's missed verdict
+            // recorded
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert_eq!(dapp.balance, 100);
+            assert_eq!(dapp.reserved, 0);
+            assert_eq!(
+                contract.get_provider_balance(provider_account),
+                provider_initial_balance
+            );
+            assert_eq!(dapp.pending_commitments, 0);
+            let stats = contract.get_provider_stats(provider_account).unwrap();
+            assert_eq!(stats.timed_out, 1);
+
+            // the provider can no longer give a verdict
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
-            let solution_id = user_root;
-            contract.provider_approve(solution_id);
-            let commitment = contract
-                .captcha_solution_commitments
-                .get(&solution_id)
-                .unwrap();
-            assert_eq!(commitment.status, CaptchaStatus::Approved);
-            let new_dapp_balance = contract.get_dapp_balance(dapp_contract_account);
-            let new_provider_balance = contract.get_provider_balance(provider_account);
-            assert_eq!(balance - Balance::from(fee), new_dapp_balance);
-            assert_eq!(balance + Balance::from(fee), new_provider_balance);
+            assert_eq!(
+                Error::CaptchaSolutionCommitmentExpired,
+                contract.provider_approve(solution_id, 0).unwrap_err()
+            );
+            assert_eq!(
+                contract
+                    .get_captcha_solution_commitment(solution_id)
+                    .unwrap()
+                    .status,
+                CaptchaStatus::Expired
+            );
+        }
 
-            // Now make sure that the provider cannot later set the solution to disapproved and make
-            // sure that the dapp balance is unchanged
-            contract.provider_disapprove(solution_id);
-            let commitment = contract
-                .captcha_solution_commitments
-                .get(&solution_id)
-                .unwrap();
-            assert_eq!(commitment.status, CaptchaStatus::Approved);
+        /// Test a dapp contract can verify its registration and reclaim it from another owner
+        #[ink::test]
+        fn test_dapp_verify() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let squatter = AccountId::from([0x2; 32]);
+            let dapp_contract_account = AccountId::from([0x3; 32]);
+            let dapp_owner = AccountId::from([0x4; 32]);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+
+            // verifying an unregistered contract fails
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
             assert_eq!(
-                balance - Balance::from(fee),
-                contract.get_dapp_balance(dapp_contract_account)
+                Error::DappDoesNotExist,
+                contract.dapp_verify(dapp_owner).unwrap_err()
             );
+
+            // registrations by other accounts are unverified
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(squatter);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert!(!dapp.verified);
+            assert_eq!(dapp.owner, squatter);
+            let squatter_initial_balance =
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(squatter)
+                    .unwrap();
+
+            // the contract reclaims the registration and the squatter's funds are returned
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
+            contract.dapp_verify(dapp_owner).unwrap();
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert!(dapp.verified);
+            assert_eq!(dapp.owner, dapp_owner);
+            assert_eq!(dapp.balance, 0);
+            assert_eq!(dapp.status, GovernanceStatus::Suspended);
             assert_eq!(
-                balance + Balance::from(fee),
-                contract.get_provider_balance(provider_account)
+                squatter_initial_balance + 100,
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(squatter)
+                    .unwrap()
             );
         }
 
-        /// Test provider cannot approve invalid solution id
+        /// Test users cannot commit solutions to a dapp until its registration is verified
         #[ink::test]
-        fn test_provider_approve_invalid_id() {
+        fn test_dapp_unverified() {
             let operator_account = AccountId::from([0x1; 32]);
-
-            // initialise the contract
             let mut contract = Prosopo::default(operator_account);
-
-            // Register the provider
+            // Register the provider and stake tokens with a dataset
             let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
             contract
                 .provider_register(service_origin, fee, Payee::Provider, provider_account)
                 .unwrap();
-
-            // Call from the provider account to add data and stake tokens
-            let balance = 100;
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
             let root = str_to_hash("merkle tree root".to_string());
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
-            contract.provider_update(service_origin, fee, Payee::Provider, provider_account);
-            // can only add data set after staking
-            // TODO test scenario where dataset is added before staking
-            contract.provider_add_dataset(root).ok();
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
 
-            // Register the dapp
+            // Register and fund the dapp, and verify it from the dapp contract
             let dapp_caller_account = AccountId::from([0x3; 32]);
             let dapp_contract_account = AccountId::from([0x4; 32]);
-
-            // Call from the dapp account
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
-            // Give the dap a balance
-            let balance = 100;
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
-            let client_origin = service_origin.clone();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
             contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
 
-            //Dapp User commit
             let dapp_user_account = AccountId::from([0x5; 32]);
-            let user_root = str_to_hash("user merkle tree root".to_string());
-            contract
-                .dapp_user_commit(dapp_contract_account, root, user_root, provider_account)
-                .ok();
 
-            // Call from the provider account to mark the wrong solution as approved
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
-            let solution_id = str_to_hash("id that does not exist".to_string());
-            let result = contract.provider_approve(solution_id);
+            // register a second dapp without the dapp contract's consent
+            let dapp_owner = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x8; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_owner);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:4343".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let user_root = str_to_hash("other user merkle tree root".to_string());
             assert_eq!(
-                Error::CaptchaSolutionCommitmentDoesNotExist,
-                result.unwrap_err()
+                Error::DappNotVerified,
+                contract
+                    .dapp_user_commit(dapp_contract_account, root, user_root, provider_account)
+                    .unwrap_err()
+            );
+            assert_eq!(
+                Error::DappNotVerified,
+                contract
+                    .dapp_is_human(dapp_contract_account, dapp_user_account)
+                    .unwrap_err()
+            );
+
+            // once the dapp contract verifies the registration users can commit solutions
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_owner).unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            assert_eq!(
+                contract
+                    .dapp_user_commit(dapp_contract_account, root, user_root, provider_account)
+                    .unwrap(),
+                CommitmentOutcome::Created
             );
+            assert!(!contract
+                .dapp_is_human(dapp_contract_account, dapp_user_account)
+                .unwrap());
         }
 
-        /// Test provider disapprove
+        /// Test a cancelled dapp can be reactivated by its owner or removed
         #[ink::test]
-        // TODO move the common stuff to a setup function
-        fn test_provider_disapprove() {
+        fn test_dapp_reactivate_and_remove() {
             let operator_account = AccountId::from([0x1; 32]);
-
-            // initialise the contract
             let mut contract = Prosopo::default(operator_account);
+            let dapp_owner = AccountId::from([0x2; 32]);
+            let dapp_contract_account = AccountId::from([0x3; 32]);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
 
-            // Register the provider
-            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_owner);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
+
+            // only deactivated dapps can be reactivated or removed
+            assert_eq!(
+                Error::DappNotDeactivated,
+                contract.dapp_reactivate(dapp_contract_account).unwrap_err()
+            );
+            assert_eq!(
+                Error::DappNotDeactivated,
+                contract.dapp_remove(dapp_contract_account).unwrap_err()
+            );
+
+            contract.dapp_cancel(dapp_contract_account).unwrap();
+            assert_eq!(
+                contract.list_dapps_by_status(vec![GovernanceStatus::Deactivated]),
+                vec![dapp_contract_account]
+            );
+            assert!(contract
+                .list_dapps_by_status(vec![GovernanceStatus::Active])
+                .is_empty());
+
+            // registering again does not reactivate the dapp
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(50);
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert_eq!(dapp.status, GovernanceStatus::Deactivated);
+            assert_eq!(dapp.balance, 0);
+
+            contract.dapp_reactivate(dapp_contract_account).unwrap();
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert_eq!(dapp.status, GovernanceStatus::Active);
+            assert_eq!(dapp.balance, 50);
+
+            // removing the dapp frees its storage
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
+            contract.dapp_cancel(dapp_contract_account).unwrap();
+            contract.dapp_remove(dapp_contract_account).unwrap();
+            assert_eq!(
+                Error::DappDoesNotExist,
+                contract
+                    .get_dapp_details(dapp_contract_account)
+                    .unwrap_err()
+            );
+            assert!(!contract.dapp_accounts.contains(&dapp_contract_account));
+        }
+
+        /// Test cancelling a dapp retains the funds reserved for pending commitments
+        #[ink::test]
+        fn test_dapp_cancel_with_pending_commitment() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 10);
             contract
                 .provider_register(service_origin, fee, Payee::Provider, provider_account)
                 .unwrap();
-
-            // Call from the provider account to add data and stake tokens
-            let balance = 100;
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
             let root = str_to_hash("merkle tree root".to_string());
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
-            contract.provider_update(service_origin, fee, Payee::Provider, provider_account);
-            // can only add data set after staking
-            contract.provider_add_dataset(root).ok();
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
 
-            // Register the dapp
+            // Register and fund the dapp, and verify it from the dapp contract
             let dapp_caller_account = AccountId::from([0x3; 32]);
             let dapp_contract_account = AccountId::from([0x4; 32]);
-
-            // Call from the dapp account
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
-            // Give the dap a balance
-            let balance = 100;
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
             let client_origin = str_to_hash("https://localhost:2424".to_string());
             contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
 
-            //Dapp User commit
+            // Commit a solution as the dapp user
             let dapp_user_account = AccountId::from([0x5; 32]);
-            let user_root = str_to_hash("user merkle tree root".to_string());
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let solution_id = str_to_hash("user merkle tree root".to_string());
             contract
-                .dapp_user_commit(dapp_contract_account, root, user_root, provider_account)
-                .ok();
-
-            // Call from the provider account to mark the solution as disapproved
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
-            let solution_id = user_root;
-            contract.provider_disapprove(solution_id);
-            let commitment = contract
-                .captcha_solution_commitments
-                .get(&solution_id)
-                .unwrap();
-            assert_eq!(commitment.status, CaptchaStatus::Disapproved);
-            let new_dapp_balance = contract.get_dapp_balance(dapp_contract_account);
-            let new_provider_balance = contract.get_provider_balance(provider_account);
-            assert_eq!(balance - Balance::from(fee), new_dapp_balance);
-            assert_eq!(balance + Balance::from(fee), new_provider_balance);
-
-            // Now make sure that the provider cannot later set the solution to approved
-            contract.provider_approve(solution_id);
-            let commitment = contract
-                .captcha_solution_commitments
-                .get(&solution_id)
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
                 .unwrap();
-            assert_eq!(commitment.status, CaptchaStatus::Disapproved);
+            let dapp_owner = AccountId::from([0x3; 32]);
+            let owner_initial_balance =
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(dapp_owner)
+                    .unwrap();
+            let provider_initial_balance = contract.get_provider_balance(provider_account);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_owner);
+            contract.dapp_cancel(dapp_contract_account).unwrap();
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert_eq!(dapp.status, GovernanceStatus::Deactivated);
+            assert_eq!(dapp.balance, 10);
+            assert_eq!(dapp.reserved, 10);
+            assert_eq!(dapp.pending_commitments, 1);
             assert_eq!(
-                balance - Balance::from(fee),
-                contract.get_dapp_balance(dapp_contract_account)
+                owner_initial_balance + 90,
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(dapp_owner)
+                    .unwrap()
             );
+
+            // the provider is still paid for the pending commitment
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            contract.provider_approve(solution_id, 0).unwrap();
             assert_eq!(
-                balance + Balance::from(fee),
+                provider_initial_balance + 10,
                 contract.get_provider_balance(provider_account)
             );
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert_eq!(dapp.balance, 0);
+            assert_eq!(dapp.reserved, 0);
+            assert_eq!(dapp.pending_commitments, 0);
         }
 
-        /// Test dapp user is human
+        /// Test the refund reserved for a commitment is paid after the dapp is cancelled
         #[ink::test]
-        // TODO probably should change the name of this function in the main contract
-        fn test_dapp_operator_is_human_user() {
+        fn test_dapp_cancel_with_reserved_refund() {
             let operator_account = AccountId::from([0x1; 32]);
-
-            // initialise the contract
             let mut contract = Prosopo::default(operator_account);
-
-            // Register the provider
-            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 0);
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 10);
             contract
                 .provider_register(service_origin, fee, Payee::Provider, provider_account)
                 .unwrap();
-
-            // Call from the provider account to add data and stake tokens
-            let balance = 100;
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
             let root = str_to_hash("merkle tree root".to_string());
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
-            contract.provider_update(service_origin, fee, Payee::Provider, provider_account);
-            // can only add data set after staking
-            contract.provider_add_dataset(root).ok();
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
 
-            // Register the dapp
+            // Register and fund the dapp, and verify it from the dapp contract
             let dapp_caller_account = AccountId::from([0x3; 32]);
             let dapp_contract_account = AccountId::from([0x4; 32]);
-
-            // Call from the dapp account
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
-            // Give the dap a balance
-            let balance = 100;
-            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(balance);
-            let client_origin = service_origin.clone();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
             contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
 
-            //Dapp User commit
             let dapp_user_account = AccountId::from([0x5; 32]);
-            // Call from the Dapp User Account
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
-            let user_root = str_to_hash("user merkle tree root".to_string());
+            let dapp_owner = AccountId::from([0x3; 32]);
+
+            // only the owner can set the refund reserve
+            assert_eq!(
+                Error::NotAuthorised,
+                contract
+                    .dapp_set_refund_reserve(dapp_contract_account, 5)
+                    .unwrap_err()
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_owner);
             contract
-                .dapp_user_commit(dapp_contract_account, root, user_root, provider_account)
-                .ok();
+                .dapp_set_refund_reserve(dapp_contract_account, 5)
+                .unwrap();
 
-            // Call from the provider account to mark the solution as disapproved
+            // the fee and the refund are reserved for commitments made from now on
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let solution_id = str_to_hash("other user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                .unwrap();
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert_eq!(dapp.reserved, 25);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_owner);
+            contract.dapp_cancel(dapp_contract_account).unwrap();
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert_eq!(dapp.balance, 25);
+
+            // the user's transaction fee is refunded from the reserved funds
+            let user_initial_balance = ink_env::test::get_account_balance::<
+                ink_env::DefaultEnvironment,
+            >(dapp_user_account)
+            .unwrap();
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
-            let solution_id = user_root;
-            contract.provider_disapprove(solution_id);
+            contract.provider_approve(solution_id, 5).unwrap();
+            assert_eq!(
+                user_initial_balance + 5,
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(
+                    dapp_user_account
+                )
+                .unwrap()
+            );
+            let dapp = contract.get_dapp_details(dapp_contract_account).unwrap();
+            assert_eq!(dapp.balance, 10);
+            assert_eq!(dapp.reserved, 10);
+            assert_eq!(dapp.pending_commitments, 1);
             let commitment = contract
-                .captcha_solution_commitments
-                .get(&solution_id)
-                .unwrap();
-            assert_eq!(commitment.status, CaptchaStatus::Disapproved);
-
-            // Now make sure that the dapp user does not pass the human test
-            let result = contract
-                .dapp_operator_is_human_user(dapp_user_account, 80)
+                .get_captcha_solution_commitment(solution_id)
                 .unwrap();
-            assert_eq!(result, false);
+            assert_eq!(commitment.status, CaptchaStatus::Approved);
         }
 
         /// Test non-existent dapp account has zero balance
@@ -2174,8 +7508,195 @@ pub mod prosopo {
             assert!(selected_provider.unwrap() == registered_provider_account.unwrap());
         }
 
+        /// Test weighted random provider selection is deterministic and skips unweighted providers
+        #[ink::test]
+        fn test_get_random_active_provider_weighted() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let mut config = contract.get_protocol_config();
+            config.provider_selection_by_reputation = true;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            contract.operator_set_protocol_config(config).unwrap();
+
+            for (id, port) in [(0x2, "2424"), (0x3, "4242")] {
+                let (provider_account, service_origin, fee) = generate_provider_data(id, port, 0);
+                contract
+                    .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                    .unwrap();
+                ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+                ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+                contract
+                    .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                    .unwrap();
+                let root = str_to_hash(format!("merkle tree root {}", port));
+                contract
+                    .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                    .unwrap();
+            }
+
+            // a provider that has lost every dispute has no weight and is never selected
+            let provider_lost = AccountId::from([0x2; 32]);
+            contract.provider_update_stats(provider_lost, |stats| {
+                stats.approved = 1;
+                stats.disputes_lost = 1;
+            });
+            assert_eq!(contract.get_provider_selection_weight(provider_lost), 0);
+
+            let user_account = AccountId::from([0x5; 32]);
+            let selected = contract.get_random_active_provider(user_account).unwrap();
+            let expected = contract.providers.get(AccountId::from([0x3; 32])).unwrap();
+            assert_eq!(selected.provider, expected);
+            // the same seed selects the same provider
+            let reselected = contract.get_random_active_provider(user_account).unwrap();
+            assert_eq!(reselected.provider, selected.provider);
+
+            // a provider whose last data set is retired is no longer selected
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(AccountId::from([0x3; 32]));
+            contract
+                .provider_retire_dataset(str_to_hash("merkle tree root 4242".to_string()))
+                .unwrap();
+            assert_eq!(
+                Error::NoActiveProviders,
+                contract
+                    .get_random_active_provider(user_account)
+                    .unwrap_err()
+            );
+        }
+
+        /// Test random provider selection over many users follows the providers' stakes
+        #[ink::test]
+        fn test_select_random_provider_distribution() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            let mut provider_ids = Vec::new();
+            for (id, port, stake) in [(0x2, "2424", 100), (0x3, "4242", 300), (0x4, "4343", 600)] {
+                let (provider_account, service_origin, fee) = generate_provider_data(id, port, 0);
+                ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+                contract
+                    .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                    .unwrap();
+                ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+                ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(stake);
+                contract
+                    .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                    .unwrap();
+                provider_ids.push(provider_account);
+            }
+
+            // each user account seeds a different draw
+            let mut counts = [0u128; 3];
+            for user in 0..3_000u16 {
+                let mut user_account = [0u8; 32];
+                user_account[..2].copy_from_slice(&user.to_le_bytes());
+                let selected = contract
+                    .select_random_provider(provider_ids.clone(), AccountId::from(user_account))
+                    .unwrap();
+                let index = provider_ids.iter().position(|id| *id == selected).unwrap();
+                counts[index] += 1;
+            }
+            for (count, expected) in counts.iter().zip([300u128, 900, 1_800]) {
+                assert!(
+                    count.abs_diff(expected) < 150,
+                    "selected {} times, expected about {}",
+                    count,
+                    expected
+                );
+            }
+        }
+
         //TODO test provider_change_status
 
+        /// Test protocol config changes apply to existing providers
+        #[ink::test]
+        fn test_protocol_config_applies_to_existing_providers() {
+            let operator_account = AccountId::from([0x1; 32]);
+            let mut contract = Prosopo::default(operator_account);
+            // Register the provider and stake tokens with a dataset
+            let (provider_account, service_origin, fee) = generate_provider_data(0x2, "4242", 10);
+            contract
+                .provider_register(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            contract
+                .provider_update(service_origin, fee, Payee::Provider, provider_account)
+                .unwrap();
+            let root = str_to_hash("merkle tree root".to_string());
+            contract
+                .provider_add_dataset(root, CaptchaType::ImageSelection, 10, 1, root)
+                .unwrap();
+
+            // Register and fund the dapp, and verify it from the dapp contract
+            let dapp_caller_account = AccountId::from([0x3; 32]);
+            let dapp_contract_account = AccountId::from([0x4; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_caller_account);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            let client_origin = str_to_hash("https://localhost:2424".to_string());
+            contract.dapp_register(client_origin, dapp_contract_account, None);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_contract_account);
+            contract.dapp_verify(dapp_caller_account).unwrap();
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
+
+            // Commit a solution as the dapp user
+            let dapp_user_account = AccountId::from([0x5; 32]);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(dapp_user_account);
+            let solution_id = str_to_hash("user merkle tree root".to_string());
+            contract
+                .dapp_user_commit(dapp_contract_account, root, solution_id, provider_account)
+                .unwrap();
+
+            // a lowered maximum fee caps the fee providers are paid
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            let mut config = contract.get_protocol_config();
+            config.provider_fee_max = 4;
+            contract.operator_set_protocol_config(config).unwrap();
+            let provider_initial_balance = contract.get_provider_balance(provider_account);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(provider_account);
+            contract.provider_approve(solution_id, 0).unwrap();
+            assert_eq!(
+                provider_initial_balance + 4,
+                contract.get_provider_balance(provider_account)
+            );
+            assert_eq!(contract.get_dapp_balance(dapp_contract_account), 96);
+
+            // providers below a raised minimum stake are excluded until they are re-evaluated
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(operator_account);
+            config.provider_stake_default = 1000;
+            contract.operator_set_protocol_config(config).unwrap();
+            assert_eq!(
+                Error::NoActiveProviders,
+                contract
+                    .get_random_active_provider(dapp_user_account)
+                    .unwrap_err()
+            );
+            assert_eq!(
+                Error::TooManyProviders,
+                contract
+                    .operator_reevaluate_providers(vec![
+                        provider_account;
+                        MAX_PROVIDER_REEVALUATION_BATCH + 1
+                    ])
+                    .unwrap_err()
+            );
+            contract
+                .operator_reevaluate_providers(vec![provider_account])
+                .unwrap();
+            assert_eq!(
+                contract
+                    .get_provider_details(provider_account)
+                    .unwrap()
+                    .status,
+                GovernanceStatus::Suspended
+            );
+        }
+
+        /// Advance the off-chain engine by `count` blocks, moving the block timestamp on with them
+        fn advance_blocks(count: u32) {
+            for _ in 0..count {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+        }
+
         /// Helper function for converting string to Hash
         fn str_to_hash(str: String) -> Hash {
             let mut result = Hash::default();